@@ -1,11 +1,62 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
 use std::io::{self, Write};
+use std::iter::Peekable;
+use std::process;
+use std::rc::Rc;
+use std::str::{Chars, FromStr};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+// Output level: 0 prints only errors, 1 is normal output and 2 adds debug detail.
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+// Normal output, hidden by --quiet.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if verbosity() >= 1 {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Normal output without a trailing newline, used for prompts.
+macro_rules! say_inline {
+    ($($arg:tt)*) => {
+        if verbosity() >= 1 {
+            print!($($arg)*);
+        }
+    };
+}
+
+// Internal details, only shown with --verbose.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if verbosity() >= 2 {
+            println!($($arg)*);
+        }
+    };
+}
 
 #[derive(Debug, Clone)]
 struct ParkingSpot {
     id: usize,
     occupied: bool,
     reserved: bool,
+    plate: Option<String>,
+    vip: bool,
+    parked_at: Option<Instant>,
+    use_count: u32,
+    held_until: Option<Instant>,
+    last_vacated: Option<Instant>,
+    last_modified: Instant,
 }
 
 impl ParkingSpot {
@@ -14,13 +65,371 @@ impl ParkingSpot {
             id,
             occupied: false,
             reserved: false,
+            plate: None,
+            vip: false,
+            parked_at: None,
+            use_count: 0,
+            held_until: None,
+            last_vacated: None,
+            last_modified: Instant::now(),
+        }
+    }
+
+    // Records that the spot changed, for incremental polling.
+    fn touch(&mut self) {
+        self.last_modified = Instant::now();
+    }
+
+    fn occupy(&mut self, plate: Option<String>) {
+        self.touch();
+        self.occupied = true;
+        self.plate = plate;
+        self.parked_at = Some(Instant::now());
+        self.use_count += 1;
+        self.held_until = None;
+    }
+
+    // Frees the spot and returns how long the car was parked.
+    fn vacate(&mut self) -> Option<Duration> {
+        self.touch();
+        self.occupied = false;
+        self.plate = None;
+        self.last_vacated = Some(Instant::now());
+        self.parked_at.take().map(|parked_at| parked_at.elapsed())
+    }
+
+    fn is_available(&self) -> bool {
+        !self.occupied && !self.reserved && self.held_until.is_none()
+    }
+
+    // VIP spots are only filled on explicit request, never by automatic assignment.
+    fn is_auto_assignable(&self) -> bool {
+        self.is_available() && !self.vip
+    }
+
+    fn symbol(&self) -> char {
+        if self.occupied {
+            'X'
+        } else if self.reserved {
+            'R'
+        } else if self.held_until.is_some() {
+            'H'
+        } else {
+            '.'
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        if self.occupied && self.reserved {
+            "Occupied (reserved)"
+        } else if self.occupied {
+            "Occupied"
+        } else if self.reserved {
+            "Reserved"
+        } else if self.held_until.is_some() {
+            "Held"
+        } else {
+            "Available"
+        }
+    }
+}
+
+impl fmt::Display for ParkingSpot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Spot {}: {}", self.id, self.status())?;
+        if self.vip {
+            write!(f, " [VIP]")?;
+        }
+        if let Some(plate) = &self.plate {
+            write!(f, " ({})", plate)?;
+        }
+        Ok(())
+    }
+}
+
+// Everything known about a single spot, gathered in one place.
+#[derive(Debug, Clone)]
+struct SpotInfo {
+    id: usize,
+    status: &'static str,
+    plate: Option<String>,
+    parked_at: Option<Instant>,
+    vip: bool,
+    held_until: Option<Instant>,
+    reservation: Option<String>,
+    confirmation_code: Option<String>,
+    claim_at: Option<Instant>,
+    payment_due: Option<Instant>,
+    use_count: u32,
+}
+
+// How `list_spots` renders the lot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ListFormat {
+    Verbose,
+    Compact,
+    Grid(usize),
+}
+
+impl ListFormat {
+    fn next(self) -> Self {
+        match self {
+            ListFormat::Verbose => ListFormat::Compact,
+            ListFormat::Compact => ListFormat::Grid(5),
+            ListFormat::Grid(_) => ListFormat::Verbose,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SortKey {
+    Id,
+    Status,
+}
+
+// A spot whose status differs between two lot states.
+#[derive(Debug, Clone, PartialEq)]
+struct SpotDiff {
+    id: usize,
+    before: &'static str,
+    after: &'static str,
+}
+
+// What removing a car set in motion: the deferred reservations it carried
+// out and, for a drive-up spot, the waitlisted reservation that took it over.
+#[derive(Debug, Clone, PartialEq)]
+struct Removal {
+    deferred: Vec<usize>,
+    promoted: Option<String>,
+}
+
+// A single mutating action, used by `ParkingLot::apply_transaction`.
+#[derive(Debug, Clone)]
+enum ParkingOp {
+    Park(usize),
+    Remove(usize),
+    Reserve(usize, String),
+    CancelReservation(usize),
+}
+
+impl FromStr for ParkingOp {
+    type Err = &'static str;
+
+    // Parses "park N", "remove N", "reserve N details" or "cancel N".
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.trim().splitn(3, ' ');
+        let action = parts.next().unwrap_or_default().to_lowercase();
+        let id: usize = parts
+            .next()
+            .and_then(|id| id.parse().ok())
+            .ok_or("Expected a spot number after the action")?;
+        match action.as_str() {
+            "park" => Ok(ParkingOp::Park(id)),
+            "remove" => Ok(ParkingOp::Remove(id)),
+            "reserve" => Ok(ParkingOp::Reserve(id, parts.next().unwrap_or_default().trim().to_string())),
+            "cancel" => Ok(ParkingOp::CancelReservation(id)),
+            _ => Err("Unknown action, expected park, remove, reserve or cancel"),
+        }
+    }
+}
+
+// A structured call into the lot, so every front end can share one
+// dispatcher (`ParkingLot::handle_request`).
+#[derive(Debug, Clone, PartialEq)]
+enum Request {
+    Park,
+    ParkInSpot(usize),
+    Remove(usize),
+    Reserve(usize, String),
+    CancelReservation(usize),
+    ClaimByCode(String, String),
+    Hold(usize, Duration),
+    Inspect(usize),
+    Status,
+}
+
+impl FromStr for Request {
+    type Err = &'static str;
+
+    // Parses "park", "park N", "remove N", "reserve N details", "cancel N",
+    // "claim CODE PLATE", "hold N MINUTES", "inspect N" or "status".
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.trim().splitn(3, ' ');
+        let action = parts.next().unwrap_or_default().to_lowercase();
+        let arg = parts.next().map(str::trim);
+        let rest = parts.next().unwrap_or_default().trim().to_string();
+        let id = || arg.and_then(|id| id.parse().ok()).ok_or("Expected a spot number after the action");
+        match action.as_str() {
+            "park" if arg.is_none() => Ok(Request::Park),
+            "park" => Ok(Request::ParkInSpot(id()?)),
+            "remove" => Ok(Request::Remove(id()?)),
+            "reserve" => Ok(Request::Reserve(id()?, rest)),
+            "cancel" => Ok(Request::CancelReservation(id()?)),
+            "claim" => {
+                let code = arg.ok_or("Expected a confirmation code after claim")?;
+                Ok(Request::ClaimByCode(code.to_string(), rest))
+            }
+            "hold" => Ok(Request::Hold(id()?, hold_minutes(&rest)?)),
+            "inspect" => Ok(Request::Inspect(id()?)),
+            "status" => Ok(Request::Status),
+            _ => Err(UNKNOWN_REQUEST),
+        }
+    }
+}
+
+const UNKNOWN_REQUEST: &str = "Unknown action, expected park, remove, reserve, cancel, claim, hold, inspect or status";
+
+fn hold_minutes(minutes: &str) -> Result<Duration, &'static str> {
+    let expected = "Expected the number of minutes to hold";
+    minutes.parse().ok().and_then(minutes_to_duration).ok_or(expected)
+}
+
+impl Request {
+    // Parses the JSON form written by `to_json`, e.g. {"action":"reserve","id":3,"details":"Amy"}.
+    fn from_json(text: &str) -> Result<Self, &'static str> {
+        let fields = parse_json_object(text)?;
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        let id = || fields.get("id").and_then(|id| id.parse().ok()).ok_or("Expected a spot number in \"id\"");
+        match fields.get("action").map(String::as_str) {
+            Some("park") if !fields.contains_key("id") => Ok(Request::Park),
+            Some("park") => Ok(Request::ParkInSpot(id()?)),
+            Some("remove") => Ok(Request::Remove(id()?)),
+            Some("reserve") => Ok(Request::Reserve(id()?, field("details"))),
+            Some("cancel") => Ok(Request::CancelReservation(id()?)),
+            Some("claim") => {
+                let code = fields.get("code").ok_or("Expected a confirmation code in \"code\"")?;
+                Ok(Request::ClaimByCode(code.clone(), field("plate")))
+            }
+            Some("hold") => Ok(Request::Hold(id()?, hold_minutes(&field("minutes"))?)),
+            Some("inspect") => Ok(Request::Inspect(id()?)),
+            Some("status") => Ok(Request::Status),
+            _ => Err(UNKNOWN_REQUEST),
+        }
+    }
+
+    // Hand-written JSON with an "action" tag, the counterpart of `Response::to_json`.
+    fn to_json(&self) -> String {
+        match self {
+            Request::Park => "{\"action\":\"park\"}".to_string(),
+            Request::ParkInSpot(id) => format!("{{\"action\":\"park\",\"id\":{}}}", id),
+            Request::Remove(id) => format!("{{\"action\":\"remove\",\"id\":{}}}", id),
+            Request::Reserve(id, details) => {
+                format!("{{\"action\":\"reserve\",\"id\":{},\"details\":{}}}", id, json_string(details))
+            }
+            Request::CancelReservation(id) => format!("{{\"action\":\"cancel\",\"id\":{}}}", id),
+            Request::ClaimByCode(code, plate) => format!(
+                "{{\"action\":\"claim\",\"code\":{},\"plate\":{}}}",
+                json_string(code),
+                json_string(plate)
+            ),
+            Request::Hold(id, ttl) => {
+                format!("{{\"action\":\"hold\",\"id\":{},\"minutes\":{}}}", id, ttl.as_secs() / 60)
+            }
+            Request::Inspect(id) => format!("{{\"action\":\"inspect\",\"id\":{}}}", id),
+            Request::Status => "{\"action\":\"status\"}".to_string(),
+        }
+    }
+}
+
+// The outcome of a `Request`, rendered for callers by `to_json`.
+#[derive(Debug, Clone, PartialEq)]
+enum Response {
+    Parked(usize),
+    Removed { deferred: Vec<usize>, promoted: Option<String> },
+    Reserved { id: usize, code: String },
+    Cancelled { promoted: Option<String> },
+    Held(usize),
+    Spot { id: usize, status: &'static str, plate: Option<String>, reservation: Option<String> },
+    Status { total: usize, occupied: usize, reserved: usize, held: usize, free: usize },
+    Error(&'static str),
+}
+
+impl Response {
+    // Hand-written JSON, since the crate has no serde. Every object carries a
+    // "result" tag naming the variant.
+    fn to_json(&self) -> String {
+        match self {
+            Response::Parked(id) => format!("{{\"result\":\"parked\",\"id\":{}}}", id),
+            Response::Removed { deferred, promoted } => {
+                let ids: Vec<String> = deferred.iter().map(usize::to_string).collect();
+                format!(
+                    "{{\"result\":\"removed\",\"deferred\":[{}],\"promoted\":{}}}",
+                    ids.join(","),
+                    json_optional(promoted.as_deref())
+                )
+            }
+            Response::Reserved { id, code } => {
+                format!("{{\"result\":\"reserved\",\"id\":{},\"code\":{}}}", id, json_string(code))
+            }
+            Response::Cancelled { promoted } => {
+                format!("{{\"result\":\"cancelled\",\"promoted\":{}}}", json_optional(promoted.as_deref()))
+            }
+            Response::Held(id) => format!("{{\"result\":\"held\",\"id\":{}}}", id),
+            Response::Spot { id, status, plate, reservation } => format!(
+                "{{\"result\":\"spot\",\"id\":{},\"status\":{},\"plate\":{},\"reservation\":{}}}",
+                id,
+                json_string(status),
+                json_optional(plate.as_deref()),
+                json_optional(reservation.as_deref())
+            ),
+            Response::Status { total, occupied, reserved, held, free } => format!(
+                "{{\"result\":\"status\",\"total\":{},\"occupied\":{},\"reserved\":{},\"held\":{},\"free\":{}}}",
+                total, occupied, reserved, held, free
+            ),
+            Response::Error(message) => format!("{{\"result\":\"error\",\"message\":{}}}", json_string(message)),
         }
     }
 }
 
+// Counts of what happened during a `ParkingLot::simulate` run.
+#[derive(Debug, Default)]
+struct SimulationSummary {
+    parks: usize,
+    removals: usize,
+    failed_parks: usize,
+}
+
+// Small linear congruential generator, so simulations are reproducible without extra crates.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+// Called with the spot ID and details whenever a reservation is cancelled.
+// Shared, so cloned lots (snapshots, transactions) keep notifying the same handler.
+type CancelHandler = Rc<RefCell<dyn FnMut(usize, &str)>>;
+
+#[derive(Clone)]
 struct ParkingLot {
     spots: Vec<ParkingSpot>,
     reservations: HashMap<usize, String>, // Map of spot ID to reservation details
+    scheduled: HashMap<usize, Instant>, // Map of spot ID to scheduled claim time
+    reservation_queues: HashMap<usize, VecDeque<String>>, // Waitlisted reservation details per spot
+    pending_payment: HashMap<usize, Instant>, // Map of spot ID to payment deadline for unconfirmed reservations
+    confirmation_codes: HashMap<usize, String>, // Map of spot ID to the reservation's confirmation code
+    reserved_at: HashMap<usize, Instant>, // Map of spot ID to when its current reservation was made
+    reserved_plates: HashMap<usize, String>, // Map of spot ID to the plate a subscriber reservation belongs to
+    drive_ups: HashSet<usize>, // Spots whose reservation was made on arrival and ends when the car leaves
+    reservation_counter: u64,
+    max_total_reservations: Option<usize>, // Lot-wide cap that keeps spots free for walk-ins
+    auto_reservation_counter: u32,
+    entrance: usize,
+    freed_history: VecDeque<(usize, Instant)>, // Most recently freed spots, newest first
+    freed_history_capacity: usize,
+    completed_stays: Vec<Duration>,
+    opened_at: Instant,
+    maintenance_mode: bool,
+    strict_plates: bool, // Reject plates that are not 2-8 letters and digits once normalized
+    last_spot_by_plate: HashMap<String, usize>,
+    fill_order: Vec<usize>, // Spot IDs in the order cars were parked in them
+    deferred_reservations: Vec<(usize, usize, String)>, // (watched spot, spot to reserve, details) waiting for the watched car to leave
+    list_format: ListFormat,
+    on_cancel: Option<CancelHandler>,
+    queued_cancellations: Option<Vec<(usize, String)>>, // Cancellations made inside a transaction, reported only once it commits
 }
 
 impl ParkingLot {
@@ -32,24 +441,108 @@ impl ParkingLot {
         Self {
             spots,
             reservations: HashMap::new(),
+            scheduled: HashMap::new(),
+            reservation_queues: HashMap::new(),
+            pending_payment: HashMap::new(),
+            confirmation_codes: HashMap::new(),
+            reserved_at: HashMap::new(),
+            reserved_plates: HashMap::new(),
+            drive_ups: HashSet::new(),
+            reservation_counter: 0,
+            max_total_reservations: None,
+            auto_reservation_counter: 0,
+            entrance: 0,
+            freed_history: VecDeque::new(),
+            freed_history_capacity: 5,
+            completed_stays: Vec::new(),
+            opened_at: Instant::now(),
+            maintenance_mode: false,
+            strict_plates: false,
+            last_spot_by_plate: HashMap::new(),
+            fill_order: Vec::new(),
+            deferred_reservations: Vec::new(),
+            list_format: ListFormat::Verbose,
+            on_cancel: None,
+            queued_cancellations: None,
         }
     }
 
     fn find_available_spot(&self) -> Option<&ParkingSpot> {
-        self.spots.iter().find(|spot| !spot.occupied && !spot.reserved)
+        self.find_spot_where(ParkingSpot::is_auto_assignable)
+    }
+
+    // First spot, in ID order, matching an arbitrary predicate. This is the
+    // escape hatch for selection logic that no dedicated finder covers.
+    fn find_spot_where<F: Fn(&ParkingSpot) -> bool>(&self, pred: F) -> Option<&ParkingSpot> {
+        self.spots.iter().find(|spot| pred(spot))
+    }
+
+    // Lightweight counters meant for cheap, frequent monitoring queries. Each
+    // spot is counted exactly once: an occupied spot counts as occupied even if
+    // it is also reserved, so free, occupied, reserved and held sum to the total.
+    fn free_count(&self) -> usize {
+        self.spots.iter().filter(|spot| spot.is_available()).count()
+    }
+
+    fn occupied_count(&self) -> usize {
+        self.spots.iter().filter(|spot| spot.occupied).count()
+    }
+
+    fn reserved_count(&self) -> usize {
+        self.spots.iter().filter(|spot| spot.reserved && !spot.occupied).count()
+    }
+
+    fn held_count(&self) -> usize {
+        self.spots
+            .iter()
+            .filter(|spot| spot.held_until.is_some() && !spot.occupied && !spot.reserved)
+            .count()
+    }
+
+    // Number of spots in each status, counted in one pass. Keys come from
+    // `ParkingSpot::status`, so a new status shows up here automatically.
+    fn status_breakdown(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for spot in &self.spots {
+            *counts.entry(spot.status()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // Terse one-line summary for status bars and scripts.
+    fn status_line(&self) -> String {
+        format!(
+            "{} spots | {} occupied | {} reserved | {} held | {} free | {:.0}% full",
+            self.spots.len(),
+            self.occupied_count(),
+            self.reserved_count(),
+            self.held_count(),
+            self.free_count(),
+            self.occupancy_ratio() * 100.0
+        )
+    }
+
+    // Occupied spots as a fraction of all spots, from 0.0 to 1.0. This is the
+    // value to feed numeric gauges; an empty lot reports 0.0.
+    fn occupancy_ratio(&self) -> f64 {
+        if self.spots.is_empty() {
+            return 0.0;
+        }
+        self.occupied_count() as f64 / self.spots.len() as f64
     }
 
     fn find_nearest_available_spot(&self, position: usize) -> Option<&ParkingSpot> {
+        self.find_nearest_to_destination(position)
+    }
+
+    // Free spot closest to where the driver is headed, e.g. an elevator or exit.
+    fn find_nearest_to_destination(&self, destination: usize) -> Option<&ParkingSpot> {
         let mut nearest_spot: Option<&ParkingSpot> = None;
         let mut min_distance = usize::MAX;
 
         for spot in &self.spots {
-            if !spot.occupied && !spot.reserved {
-                let distance = if spot.id >= position {
-                    spot.id - position
-                } else {
-                    position - spot.id
-                };
+            if spot.is_auto_assignable() {
+                let distance = spot.id.abs_diff(destination);
 
                 if distance < min_distance {
                     min_distance = distance;
@@ -60,50 +553,213 @@ impl ParkingLot {
         nearest_spot
     }
 
-    fn park_car(&mut self) -> Result<usize, &'static str> {
-        if let Some(spot) = self.spots.iter_mut().find(|spot| !spot.occupied && !spot.reserved) {
-            spot.occupied = true;
-            Ok(spot.id)
-        } else {
-            Err("No available spots")
-        }
+    // Lowest available spot ID at or after `id`. Unlike the nearest-spot search
+    // this never looks backwards.
+    fn next_available_after(&self, id: usize) -> Option<usize> {
+        self.spots.iter().skip(id).find(|spot| spot.is_auto_assignable()).map(|spot| spot.id)
     }
 
-    fn park_car_in_spot(&mut self, id: usize) -> Result<(), &'static str> {
+    // Spot IDs within `radius` of `id`, not including `id` itself.
+    fn neighbors(&self, id: usize, radius: usize) -> Vec<usize> {
         if id >= self.spots.len() {
-            return Err("Invalid spot ID");
+            return Vec::new();
         }
-        let spot = &mut self.spots[id];
-        if spot.occupied || spot.reserved {
-            return Err("Spot already occupied or reserved");
+        let start = id.saturating_sub(radius);
+        let end = id.saturating_add(radius).min(self.spots.len() - 1);
+        (start..=end).filter(|&other| other != id).collect()
+    }
+
+    fn available_neighbors(&self, id: usize, radius: usize) -> Vec<usize> {
+        self.neighbors(id, radius)
+            .into_iter()
+            .filter(|&other| self.spots[other].is_available())
+            .collect()
+    }
+
+    // The available spot closest to the middle of the lot, ties going to the lower ID.
+    fn find_middle_available_spot(&self) -> Option<usize> {
+        // Compare doubled IDs so an even-sized lot's midpoint stays a whole number.
+        let middle = self.spots.len().saturating_sub(1);
+        self.spots
+            .iter()
+            .filter(|spot| spot.is_auto_assignable())
+            .min_by_key(|spot| ((spot.id * 2).abs_diff(middle), spot.id))
+            .map(|spot| spot.id)
+    }
+
+    // Like `find_nearest_available_spot`, but reserved spots count too. The
+    // flag tells the caller whether the returned spot is reserved.
+    fn find_nearest_including_reserved(&self, position: usize) -> Option<(&ParkingSpot, bool)> {
+        self.spots
+            .iter()
+            .filter(|spot| spot.is_auto_assignable() || (spot.reserved && !spot.occupied))
+            .min_by_key(|spot| (spot.id.abs_diff(position), spot.id))
+            .map(|spot| (spot, spot.reserved))
+    }
+
+    fn find_nearest_from_entrance(&self) -> Option<&ParkingSpot> {
+        self.find_nearest_available_spot(self.entrance)
+    }
+
+    // Mean distance from `entrance` to every occupied spot, or None if the lot is empty.
+    fn average_fill_distance(&self, entrance: usize) -> Option<f64> {
+        let distances: Vec<usize> = self
+            .spots
+            .iter()
+            .filter(|spot| spot.occupied)
+            .map(|spot| spot.id.abs_diff(entrance))
+            .collect();
+        if distances.is_empty() {
+            return None;
         }
-        spot.occupied = true;
+        Some(distances.iter().sum::<usize>() as f64 / distances.len() as f64)
+    }
+
+    // Sum of distances from `entrance` to every occupied spot, for comparing
+    // how much walking each parking strategy causes. 0 for an empty lot.
+    fn total_walk_distance(&self, entrance: usize) -> usize {
+        self.spots
+            .iter()
+            .filter(|spot| spot.occupied)
+            .map(|spot| spot.id.abs_diff(entrance))
+            .sum()
+    }
+
+    fn set_entrance(&mut self, position: usize) -> Result<(), &'static str> {
+        if position >= self.spots.len() {
+            return Err("Invalid entrance position");
+        }
+        self.entrance = position;
         Ok(())
     }
 
-    fn remove_car(&mut self, id: usize) -> Result<(), &'static str> {
-        if let Some(spot) = self.spots.iter_mut().find(|spot| spot.id == id && spot.occupied) {
-            spot.occupied = false;
-            Ok(())
-        } else {
-            Err("Spot not found or already empty")
+    // While maintenance mode is on, cars can leave but nothing can be parked or reserved.
+    fn set_maintenance(&mut self, on: bool) {
+        self.maintenance_mode = on;
+    }
+
+    fn ensure_open(&self) -> Result<(), &'static str> {
+        if self.maintenance_mode {
+            return Err("Lot is closed for maintenance");
         }
+        Ok(())
     }
 
-    fn list_spots(&self) {
-        for spot in &self.spots {
-            let status = if spot.occupied {
-                "Occupied"
-            } else if spot.reserved {
-                "Reserved"
-            } else {
-                "Available"
-            };
-            println!("Spot {}: {}", spot.id, status);
+    // Normalizes a plate for storage. Plates with no letters or digits are
+    // always rejected; strict mode also rejects malformed ones.
+    fn plate_for(&self, plate: &str) -> Result<String, &'static str> {
+        let plate = normalize_plate(plate);
+        if plate.is_empty() || self.strict_plates && !(2..=8).contains(&plate.len()) {
+            return Err("Invalid license plate");
         }
+        Ok(plate)
     }
 
-    fn reserve_spot(&mut self, id: usize, details: String) -> Result<(), &'static str> {
+    // Whether a car with this plate is parked right now, ignoring case and
+    // punctuation. This is the membership check to use for gate systems.
+    fn is_parked(&self, plate: &str) -> bool {
+        let plate = normalize_plate(plate);
+        self.spots
+            .iter()
+            .any(|spot| spot.occupied && spot.plate.as_deref() == Some(plate.as_str()))
+    }
+
+    // Every park path ends here, so lot-wide bookkeeping stays in one place.
+    fn occupy_spot(&mut self, id: usize, plate: Option<String>) {
+        if let Some(plate) = &plate {
+            self.last_spot_by_plate.insert(plate.clone(), id);
+        }
+        self.spots[id].occupy(plate);
+        self.fill_order.push(id);
+    }
+
+    // Every spot filled so far, in the order it was filled. Removals do not
+    // shorten it, and cars moved by compaction are not recorded again.
+    fn fill_order(&self) -> &[usize] {
+        &self.fill_order
+    }
+
+    // The spot a plate was last parked in, even if the car has since left.
+    // Use `is_parked` to ask whether the car is here now.
+    fn last_known_spot(&self, plate: &str) -> Option<usize> {
+        self.last_spot_by_plate.get(&normalize_plate(plate)).copied()
+    }
+
+    // Parks the car in the spot it used last time if that spot is free,
+    // otherwise in the nearest available spot to the entrance.
+    fn park_preferred(&mut self, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let id = match self.last_spot_by_plate.get(&plate) {
+            Some(&id) if self.spots[id].is_auto_assignable() => id,
+            _ => self.find_nearest_from_entrance().ok_or("No available spots")?.id,
+        };
+        self.occupy_spot(id, Some(plate));
+        Ok(id)
+    }
+
+    fn park_car(&mut self) -> Result<usize, &'static str> {
+        self.park_where(ParkingSpot::is_auto_assignable)
+    }
+
+    // Parks each plate in the next available spot, returning one result per plate.
+    fn park_batch(&mut self, plates: Vec<String>) -> Vec<Result<usize, &'static str>> {
+        plates
+            .into_iter()
+            .map(|plate| {
+                self.ensure_open()?;
+                let plate = self.plate_for(&plate)?;
+                let id = self.find_available_spot().ok_or("No available spots")?.id;
+                self.occupy_spot(id, Some(plate));
+                Ok(id)
+            })
+            .collect()
+    }
+
+    // Parks in the first available spot matching `pred`, for custom selection
+    // logic. Unlike automatic parking, the predicate decides about VIP spots.
+    fn park_where<F: Fn(&ParkingSpot) -> bool>(&mut self, pred: F) -> Result<usize, &'static str> {
+        self.ensure_open()?;
+        let id = self
+            .find_spot_where(|spot| spot.is_available() && pred(spot))
+            .ok_or("No available spots")?
+            .id;
+        self.occupy_spot(id, None);
+        Ok(id)
+    }
+
+    // Parks in the free spot farthest from any occupied spot, leaving the most
+    // room around the car. Ties go to the lowest ID.
+    fn park_spread(&mut self, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let occupied: Vec<usize> = self.spots.iter().filter(|spot| spot.occupied).map(|spot| spot.id).collect();
+        let id = self
+            .spots
+            .iter()
+            .filter(|spot| spot.is_auto_assignable())
+            .map(|spot| {
+                let gap = occupied.iter().map(|&other| other.abs_diff(spot.id)).min().unwrap_or(usize::MAX);
+                (spot.id, gap)
+            })
+            .min_by_key(|&(id, gap)| (Reverse(gap), id))
+            .map(|(id, _)| id)
+            .ok_or("No available spots")?;
+
+        self.occupy_spot(id, Some(plate));
+        Ok(id)
+    }
+
+    fn park_middle(&mut self, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let id = self.find_middle_available_spot().ok_or("No available spots")?;
+        self.occupy_spot(id, Some(plate));
+        Ok(id)
+    }
+
+    fn park_car_in_spot(&mut self, id: usize) -> Result<(), &'static str> {
+        self.ensure_open()?;
         if id >= self.spots.len() {
             return Err("Invalid spot ID");
         }
@@ -111,111 +767,2191 @@ impl ParkingLot {
         if spot.occupied || spot.reserved {
             return Err("Spot already occupied or reserved");
         }
-        spot.reserved = true;
-        self.reservations.insert(id, details);
+        if spot.held_until.is_some() {
+            return Err("Spot is held for another customer");
+        }
+        if spot.vip {
+            return Err("Spot is reserved for VIP parking");
+        }
+        self.occupy_spot(id, None);
         Ok(())
     }
 
-    fn cancel_reservation(&mut self, id: usize) -> Result<(), &'static str> {
-        if id >= self.spots.len() || !self.spots[id].reserved {
-            return Err("Invalid spot ID or spot not reserved");
+    fn park_vip(&mut self, id: usize, plate: String) -> Result<(), &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let spot = self.spots.get_mut(id).ok_or("Invalid spot ID")?;
+        if !spot.vip {
+            return Err("Spot is not a VIP spot");
         }
-        self.spots[id].reserved = false;
-        self.reservations.remove(&id);
+        if spot.occupied || spot.reserved {
+            return Err("Spot already occupied or reserved");
+        }
+        if spot.held_until.is_some() {
+            return Err("Spot is held for another customer");
+        }
+        self.occupy_spot(id, Some(plate));
         Ok(())
     }
-}
 
-fn display_help() {
-    println!("Parking Lot Help:");
-    println!("1. Park car in next available spot: Automatically parks your car in the next available spot.");
-    println!("2. Park car in specific spot: Allows you to choose a specific spot to park your car.");
-    println!("3. Remove car from spot: Removes the car from the specified spot.");
-    println!("4. List all spots: Displays the status of all parking spots (Occupied, Reserved, or Available).");
-    println!("5. Find nearest available spot: Finds the nearest available spot from your current position.");
-    println!("6. Reserve a spot in advance: Allows you to reserve a parking spot for future use.");
-    println!("7. Cancel a reservation: Cancels an existing reservation for a spot.");
-    println!("8. Exit: Exits the parking lot system.");
-    println!("9. Help: Displays this help information.");
-}
+    fn set_vip(&mut self, id: usize, vip: bool) -> Result<(), &'static str> {
+        let spot = self.spots.get_mut(id).ok_or("Invalid spot ID")?;
+        spot.touch();
+        spot.vip = vip;
+        Ok(())
+    }
 
-fn main() {
-    let mut parking_lot = ParkingLot::new(10);
+    // Frees an occupied spot and returns the spots reserved by deferred
+    // reservations that were waiting for it.
+    fn remove_car(&mut self, id: usize) -> Result<Removal, &'static str> {
+        if let Some(spot) = self.spots.iter_mut().find(|spot| spot.id == id && spot.occupied) {
+            if let Some(stay) = spot.vacate() {
+                self.completed_stays.push(stay);
+            }
+            // A drive-up reservation ends when its car leaves. Any other
+            // reservation, e.g. one promoted from the waitlist, now gets the spot.
+            let mut promoted = None;
+            if self.drive_ups.contains(&id) {
+                self.clear_reservation(id);
+                promoted = self.promote_queued(id);
+            }
+            self.freed_history.push_front((id, Instant::now()));
+            self.freed_history.truncate(self.freed_history_capacity);
+            Ok(Removal { deferred: self.fulfill_deferred(id), promoted })
+        } else {
+            Err("Spot not found or already empty")
+        }
+    }
 
-    loop {
-        println!("\nParking Lot Menu:");
-        println!("1. Park car in next available spot");
-        println!("2. Park car in specific spot");
-        println!("3. Remove car from spot");
-        println!("4. List all spots");
-        println!("5. Find nearest available spot");
-        println!("6. Reserve a spot in advance");
-        println!("7. Cancel a reservation");
-        println!("8. Exit");
-        println!("9. Help");
-        print!("Choose an option: ");
-        io::stdout().flush().unwrap();
+    // Reserves `reserve_id` for `details` once the car in `watch_id` leaves,
+    // e.g. for a customer swapping spots.
+    fn reserve_on_free(&mut self, watch_id: usize, reserve_id: usize, details: String) -> Result<(), &'static str> {
+        self.ensure_open()?;
+        if watch_id >= self.spots.len() || reserve_id >= self.spots.len() {
+            return Err("Invalid spot ID");
+        }
+        if !self.spots[watch_id].occupied {
+            return Err("Watched spot is already free, reserve directly instead");
+        }
+        self.deferred_reservations.push((watch_id, reserve_id, details));
+        Ok(())
+    }
 
-        let mut choice = String::new();
-        io::stdin().read_line(&mut choice).unwrap();
-        let choice: u32 = match choice.trim().parse() {
-            Ok(num) => num,
-            Err(_) => continue,
-        };
+    // Carries out the deferred reservations waiting on `freed`, in the order
+    // they were made. Ones whose target was taken in the meantime are dropped;
+    // ones blocked by maintenance or the quota are kept for `retry_deferred`.
+    fn fulfill_deferred(&mut self, freed: usize) -> Vec<usize> {
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.deferred_reservations)
+            .into_iter()
+            .partition(|&(watch_id, _, _)| watch_id == freed);
+        self.deferred_reservations = waiting;
+        self.carry_out_deferred(due)
+    }
 
-        match choice {
-            1 => {
-                match parking_lot.park_car() {
-                    Ok(id) => println!("Car parked in spot {}", id),
-                    Err(err) => println!("Error: {}", err),
-                }
-            }
-            2 => {
-                print!("Enter the spot number where you want to park the car: ");
-                io::stdout().flush().unwrap();
-                let mut spot = String::new();
-                io::stdin().read_line(&mut spot).unwrap();
-                let spot: usize = match spot.trim().parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid spot number.");
-                        continue;
-                    },
-                };
+    // Retries deferred reservations whose watched car already left but which
+    // could not be made at the time, e.g. once the lot reopens.
+    fn retry_deferred(&mut self) -> Vec<usize> {
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.deferred_reservations)
+            .into_iter()
+            .partition(|&(watch_id, _, _)| !self.spots[watch_id].occupied);
+        self.deferred_reservations = waiting;
+        self.carry_out_deferred(due)
+    }
 
-                match parking_lot.park_car_in_spot(spot) {
-                    Ok(_) => println!("Car parked in spot {}", spot),
-                    Err(err) => println!("Error: {}", err),
-                }
+    fn carry_out_deferred(&mut self, due: Vec<(usize, usize, String)>) -> Vec<usize> {
+        let mut fulfilled = Vec::new();
+        for (watch_id, id, details) in due {
+            if self.maintenance_mode || self.reservation_quota_left() == 0 {
+                self.deferred_reservations.push((watch_id, id, details));
+            } else if self.reserve_spot(id, details).is_ok() {
+                fulfilled.push(id);
             }
-            3 => {
-                print!("Enter the spot number to remove the car from: ");
-                io::stdout().flush().unwrap();
-                let mut spot = String::new();
-                io::stdin().read_line(&mut spot).unwrap();
-                let spot: usize = match spot.trim().parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        println!("Invalid input. Please enter a valid spot number.");
-                        continue;
-                    },
-                };
+        }
+        fulfilled
+    }
 
-                match parking_lot.remove_car(spot) {
-                    Ok(_) => println!("Car removed from spot {}", spot),
-                    Err(err) => println!("Error: {}", err),
-                }
-            }
+    // Returns the most recently freed spots, newest first, with how long ago each was freed.
+    fn recently_freed(&self) -> Vec<(usize, Duration)> {
+        self.freed_history
+            .iter()
+            .map(|&(id, freed_at)| (id, freed_at.elapsed()))
+            .collect()
+    }
+
+    fn set_freed_history_capacity(&mut self, capacity: usize) {
+        self.freed_history_capacity = capacity;
+        self.freed_history.truncate(capacity);
+    }
+
+    // How long the lot has been open, i.e. the reporting window for rates.
+    fn session_duration(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.opened_at)
+    }
+
+    // Cars served per spot per hour since the lot opened. Returns 0.0 during
+    // the first second so early readings do not blow up.
+    fn turnover_rate(&self, now: Instant) -> f64 {
+        let elapsed = self.session_duration(now);
+        if self.spots.is_empty() || elapsed < Duration::from_secs(1) {
+            return 0.0;
+        }
+        self.completed_stays.len() as f64 / self.spots.len() as f64 / (elapsed.as_secs_f64() / 3600.0)
+    }
+
+    fn average_stay(&self) -> Option<Duration> {
+        if self.completed_stays.is_empty() {
+            return None;
+        }
+        let total: Duration = self.completed_stays.iter().sum();
+        Some(total / self.completed_stays.len() as u32)
+    }
+
+    fn median_stay(&self) -> Option<Duration> {
+        if self.completed_stays.is_empty() {
+            return None;
+        }
+        let mut stays = self.completed_stays.clone();
+        stays.sort_unstable();
+        let mid = stays.len() / 2;
+        if stays.len().is_multiple_of(2) {
+            Some((stays[mid - 1] + stays[mid]) / 2)
+        } else {
+            Some(stays[mid])
+        }
+    }
+
+    // Runs `steps` pseudo-random park/remove operations. The same seed always
+    // produces the same sequence.
+    fn simulate(&mut self, steps: usize, seed: u64) -> SimulationSummary {
+        let mut rng = Lcg(seed);
+        let mut summary = SimulationSummary::default();
+        for _ in 0..steps {
+            if rng.next().is_multiple_of(2) {
+                match self.park_car() {
+                    Ok(_) => summary.parks += 1,
+                    Err(_) => summary.failed_parks += 1,
+                }
+            } else {
+                let occupied: Vec<usize> = self.spots.iter().filter(|spot| spot.occupied).map(|spot| spot.id).collect();
+                if !occupied.is_empty() {
+                    let id = occupied[rng.next() as usize % occupied.len()];
+                    if self.remove_car(id).is_ok() {
+                        summary.removals += 1;
+                    }
+                }
+            }
+        }
+        summary
+    }
+
+    // Gini coefficient of spot use counts: 0.0 when every spot has been used
+    // equally, approaching 1.0 when a few spots take all the cars.
+    fn usage_gini(&self) -> f64 {
+        let mut counts: Vec<u64> = self.spots.iter().map(|spot| u64::from(spot.use_count)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        counts.sort_unstable();
+        let n = counts.len() as f64;
+        let weighted: f64 = counts.iter().enumerate().map(|(i, &count)| (i + 1) as f64 * count as f64).sum();
+        2.0 * weighted / (n * total as f64) - (n + 1.0) / n
+    }
+
+    // The `n` least used spots, fewest uses first, ties broken by ID.
+    fn least_used(&self, n: usize) -> Vec<(usize, u32)> {
+        let mut usage: Vec<(usize, u32)> = self.spots.iter().map(|spot| (spot.id, spot.use_count)).collect();
+        usage.sort_by_key(|&(id, uses)| (uses, id));
+        usage.truncate(n);
+        usage
+    }
+
+    // Applies all operations or none: if any operation fails, the lot is
+    // restored to its state from before the transaction.
+    fn apply_transaction(&mut self, ops: &[ParkingOp]) -> Result<(), &'static str> {
+        let snapshot = self.clone();
+        self.queued_cancellations = Some(Vec::new());
+        for op in ops {
+            let result = match op {
+                ParkingOp::Park(id) => self.park_car_in_spot(*id),
+                ParkingOp::Remove(id) => self.remove_car(*id).map(|_| ()),
+                ParkingOp::Reserve(id, details) => self.reserve_spot(*id, details.clone()),
+                ParkingOp::CancelReservation(id) => self.cancel_reservation(*id).map(|_| ()),
+            };
+            if let Err(err) = result {
+                *self = snapshot;
+                return Err(err);
+            }
+        }
+        for (id, details) in self.queued_cancellations.take().unwrap_or_default() {
+            self.notify_cancel(id, &details);
+        }
+        Ok(())
+    }
+
+    // Spots used more than `threshold` times, most used first.
+    fn spots_over_uses(&self, threshold: u32) -> Vec<usize> {
+        let mut worn: Vec<&ParkingSpot> = self.spots.iter().filter(|spot| spot.use_count > threshold).collect();
+        worn.sort_by_key(|spot| (Reverse(spot.use_count), spot.id));
+        worn.into_iter().map(|spot| spot.id).collect()
+    }
+
+    // The `n` occupied spots whose cars have been parked longest, longest first.
+    fn longest_parked(&self, n: usize) -> Vec<(usize, Duration)> {
+        let mut parked: Vec<(usize, Duration)> = self
+            .spots
+            .iter()
+            .filter_map(|spot| spot.parked_at.map(|parked_at| (spot.id, parked_at.elapsed())))
+            .collect();
+        parked.sort_by_key(|&(id, elapsed)| (Reverse(elapsed), id));
+        parked.truncate(n);
+        parked
+    }
+
+    // Removes the car from each spot in turn, returning one result per spot.
+    fn remove_cars(&mut self, ids: &[usize]) -> Vec<Result<Removal, &'static str>> {
+        ids.iter().map(|&id| self.remove_car(id)).collect()
+    }
+
+    // Appends `other`'s spots after this lot's, shifting their IDs by
+    // `id_offset` and carrying over reservations, waitlists and history. Spot
+    // IDs index the spot list, so the offset must be exactly this lot's size.
+    // Both lots issue codes from their own counters, so `other`'s reservations
+    // get fresh confirmation codes from this lot.
+    fn merge(&mut self, other: ParkingLot, id_offset: usize) -> Result<(), &'static str> {
+        if id_offset < self.spots.len() {
+            return Err("Merged spot IDs would collide with existing spots");
+        }
+        if id_offset > self.spots.len() {
+            return Err("Merged spot IDs would leave a gap after existing spots");
+        }
+        for mut spot in other.spots {
+            spot.id += id_offset;
+            self.spots.push(spot);
+        }
+        self.reservations.extend(other.reservations.into_iter().map(|(id, details)| (id + id_offset, details)));
+        self.scheduled.extend(other.scheduled.into_iter().map(|(id, at)| (id + id_offset, at)));
+        self.reservation_queues.extend(other.reservation_queues.into_iter().map(|(id, queue)| (id + id_offset, queue)));
+        self.pending_payment.extend(other.pending_payment.into_iter().map(|(id, due)| (id + id_offset, due)));
+        let mut coded: Vec<usize> = other.confirmation_codes.into_keys().collect();
+        coded.sort_unstable();
+        for id in coded {
+            let code = self.next_confirmation_code(id + id_offset);
+            self.confirmation_codes.insert(id + id_offset, code);
+        }
+        self.reserved_at.extend(other.reserved_at.into_iter().map(|(id, at)| (id + id_offset, at)));
+        self.reserved_plates.extend(other.reserved_plates.into_iter().map(|(id, plate)| (id + id_offset, plate)));
+        self.drive_ups.extend(other.drive_ups.into_iter().map(|id| id + id_offset));
+        // Keep the reference counter ahead of both lots so new references stay unique.
+        self.auto_reservation_counter = self.auto_reservation_counter.max(other.auto_reservation_counter);
+        self.freed_history.extend(other.freed_history.into_iter().map(|(id, at)| (id + id_offset, at)));
+        self.freed_history.make_contiguous().sort_by_key(|&(_, at)| Reverse(at));
+        self.freed_history.truncate(self.freed_history_capacity);
+        self.completed_stays.extend(other.completed_stays);
+        for (plate, id) in other.last_spot_by_plate {
+            self.last_spot_by_plate.entry(plate).or_insert(id + id_offset);
+        }
+        self.fill_order.extend(other.fill_order.into_iter().map(|id| id + id_offset));
+        self.deferred_reservations.extend(
+            other
+                .deferred_reservations
+                .into_iter()
+                .map(|(watch_id, id, details)| (watch_id + id_offset, id + id_offset, details)),
+        );
+        Ok(())
+    }
+
+    // Spots whose status differs between `self` (before) and `other` (after).
+    // Spots that exist in only one of the lots are reported as "Missing" on the other side.
+    fn diff(&self, other: &ParkingLot) -> Vec<SpotDiff> {
+        let len = self.spots.len().max(other.spots.len());
+        (0..len)
+            .filter_map(|id| {
+                let before = self.spots.get(id).map_or("Missing", |spot| spot.status());
+                let after = other.spots.get(id).map_or("Missing", |spot| spot.status());
+                (before != after).then_some(SpotDiff { id, before, after })
+            })
+            .collect()
+    }
+
+    // Available spots ordered by how long they have been empty, longest first.
+    // Spots that have never been used count as vacant forever (Duration::MAX).
+    fn longest_vacant(&self) -> Vec<(usize, Duration)> {
+        let mut vacant: Vec<(usize, Duration)> = self
+            .spots
+            .iter()
+            .filter(|spot| spot.is_auto_assignable())
+            .map(|spot| (spot.id, spot.last_vacated.map_or(Duration::MAX, |at| at.elapsed())))
+            .collect();
+        vacant.sort_by_key(|&(id, vacant_for)| (Reverse(vacant_for), id));
+        vacant
+    }
+
+    fn park_longest_vacant(&mut self, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let (id, _) = *self.longest_vacant().first().ok_or("No available spots")?;
+        self.occupy_spot(id, Some(plate));
+        Ok(id)
+    }
+
+    // Rough time until a spot frees up: the smallest remaining time among parked
+    // cars, assuming each stays for the average completed stay. None without data.
+    fn estimated_wait(&self) -> Option<Duration> {
+        let average = self.average_stay()?;
+        self.spots
+            .iter()
+            .filter_map(|spot| spot.parked_at)
+            .map(|parked_at| average.saturating_sub(parked_at.elapsed()))
+            .min()
+    }
+
+    // Moves parked cars into the lowest-numbered free spots, farthest car
+    // first, and returns the (from, to) moves. Cars in reserved or VIP spots
+    // stay put, and cars only move into spots automatic parking could use.
+    fn compact(&mut self) -> Vec<(usize, usize)> {
+        let mut moves = Vec::new();
+        loop {
+            let from = self
+                .spots
+                .iter()
+                .rev()
+                .find(|spot| spot.occupied && !spot.reserved && !spot.vip)
+                .map(|spot| spot.id);
+            let to = self.find_available_spot().map(|spot| spot.id);
+            match (from, to) {
+                (Some(from), Some(to)) if to < from => {
+                    self.move_car(from, to);
+                    moves.push((from, to));
+                }
+                _ => break,
+            }
+        }
+        moves
+    }
+
+    // Moves a car between spots, keeping its plate and entry time. A move is
+    // not a new park, so use counts and the fill order are left alone, and
+    // deferred reservations watching the car follow it to its new spot.
+    fn move_car(&mut self, from: usize, to: usize) {
+        let plate = self.spots[from].plate.clone();
+        let parked_at = self.spots[from].parked_at;
+        self.spots[from].vacate();
+        if let Some(plate) = &plate {
+            self.last_spot_by_plate.insert(plate.clone(), to);
+        }
+        for (watch_id, _, _) in &mut self.deferred_reservations {
+            if *watch_id == from {
+                *watch_id = to;
+            }
+        }
+        let spot = &mut self.spots[to];
+        spot.touch();
+        spot.occupied = true;
+        spot.plate = plate;
+        spot.parked_at = parked_at;
+    }
+
+    // IDs of spots changed after `since`, in ID order.
+    fn modified_since(&self, since: Instant) -> Vec<usize> {
+        self.spots
+            .iter()
+            .filter(|spot| spot.last_modified > since)
+            .map(|spot| spot.id)
+            .collect()
+    }
+
+    // Deterministic dump of every spot's state in ID order, for comparing lots
+    // in tests. Timestamps are left out so identical lots always match.
+    fn snapshot_string(&self) -> String {
+        self.spots
+            .iter()
+            .map(|spot| {
+                format!(
+                    "{} occupied={} reserved={} held={} vip={} plate={:?} reservation={:?} code={:?} uses={}\n",
+                    spot.id,
+                    spot.occupied,
+                    spot.reserved,
+                    spot.held_until.is_some(),
+                    spot.vip,
+                    spot.plate,
+                    self.reservations.get(&spot.id),
+                    self.confirmation_code(spot.id),
+                    spot.use_count
+                )
+            })
+            .collect()
+    }
+
+    // Runs of consecutive occupied spots as (start ID, length).
+    fn occupied_clusters(&self) -> Vec<(usize, usize)> {
+        let mut clusters: Vec<(usize, usize)> = Vec::new();
+        for spot in self.spots.iter().filter(|spot| spot.occupied) {
+            match clusters.last_mut() {
+                Some((start, len)) if *start + *len == spot.id => *len += 1,
+                _ => clusters.push((spot.id, 1)),
+            }
+        }
+        clusters
+    }
+
+    // Prints the lot in the preferred list format.
+    fn list_spots(&self) {
+        if self.maintenance_mode {
+            say!("*** MAINTENANCE: the lot is closed for parking ***");
+        }
+        say_inline!("{}", self.list(self.list_format));
+    }
+
+    fn list(&self, format: ListFormat) -> String {
+        match format {
+            ListFormat::Verbose => self.render_list(),
+            ListFormat::Compact => self.render_compact(),
+            ListFormat::Grid(columns) => self.render_grid(columns),
+        }
+    }
+
+    // One status line per spot.
+    fn render_list(&self) -> String {
+        self.spots.iter().map(|spot| format!("{}\n", spot)).collect()
+    }
+
+    // All spot symbols on a single line, in ID order.
+    fn render_compact(&self) -> String {
+        let mut line: String = self.spots.iter().map(|spot| spot.symbol()).collect();
+        line.push('\n');
+        line
+    }
+
+    // Lists spots ordered by `by`. The sort is stable, so spots with the same
+    // status stay in ID order.
+    fn list_spots_sorted(&self, by: SortKey) {
+        let mut spots: Vec<&ParkingSpot> = self.spots.iter().collect();
+        match by {
+            SortKey::Id => spots.sort_by_key(|spot| spot.id),
+            // Occupied first, then reserved, then held, then available.
+            SortKey::Status => spots.sort_by_key(|spot| (!spot.occupied, !spot.reserved, spot.held_until.is_none())),
+        }
+        for spot in spots {
+            say!("{}", spot);
+        }
+    }
+
+    fn spot_to_json(&self, id: usize) -> Result<String, &'static str> {
+        let spot = self.spots.get(id).ok_or("Invalid spot ID")?;
+        Ok(format!(
+            "{{\"id\":{},\"status\":{},\"vip\":{},\"plate\":{},\"reservation\":{}}}",
+            spot.id,
+            json_string(spot.status()),
+            spot.vip,
+            json_optional(spot.plate.as_deref()),
+            json_optional(self.reservations.get(&id).map(String::as_str))
+        ))
+    }
+
+    // Arranges the spots row by row into `columns` columns. The last row is
+    // shorter when the lot size is not a multiple of `columns`.
+    fn as_grid(&self, columns: usize) -> Vec<Vec<&ParkingSpot>> {
+        if columns == 0 {
+            return Vec::new();
+        }
+        self.spots.chunks(columns).map(|row| row.iter().collect()).collect()
+    }
+
+    // Text grid of status symbols (X occupied, R reserved, H held, . available)
+    // with column numbers across the top and each row's first spot ID on the left.
+    fn render_grid(&self, columns: usize) -> String {
+        let width = self.spots.len().max(columns).to_string().len();
+        let mut out = format!("{:width$}", "", width = width);
+        for column in 0..columns {
+            out.push_str(&format!(" {:>width$}", column, width = width));
+        }
+        out.push('\n');
+        for (row, spots) in self.as_grid(columns).iter().enumerate() {
+            out.push_str(&format!("{:>width$}", row * columns, width = width));
+            for column in 0..columns {
+                let symbol = spots.get(column).map_or(' ', |spot| spot.symbol());
+                out.push_str(&format!(" {:>width$}", symbol, width = width));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn list_spot(&self, id: usize) -> Result<String, &'static str> {
+        self.spots.get(id).map(|spot| spot.to_string()).ok_or("Invalid spot ID")
+    }
+
+    // Runs one request and wraps its outcome, turning failures into
+    // `Response::Error` so callers only ever deal with a response.
+    fn handle_request(&mut self, req: Request) -> Response {
+        let result = match req {
+            Request::Park => self.park_car().map(Response::Parked),
+            Request::ParkInSpot(id) => self.park_car_in_spot(id).map(|_| Response::Parked(id)),
+            Request::Remove(id) => self
+                .remove_car(id)
+                .map(|Removal { deferred, promoted }| Response::Removed { deferred, promoted }),
+            Request::Reserve(id, details) => self.reserve_spot(id, details).map(|_| Response::Reserved {
+                id,
+                code: self.confirmation_code(id).unwrap_or_default().to_string(),
+            }),
+            Request::CancelReservation(id) => {
+                self.cancel_reservation(id).map(|promoted| Response::Cancelled { promoted })
+            }
+            Request::ClaimByCode(code, plate) => self.claim_by_code(&code, plate).map(Response::Parked),
+            Request::Hold(id, ttl) => self.hold_spot(id, ttl).map(|_| Response::Held(id)),
+            Request::Inspect(id) => self.spot_info(id).map(|info| Response::Spot {
+                id: info.id,
+                status: info.status,
+                plate: info.plate,
+                reservation: info.reservation,
+            }),
+            Request::Status => Ok(Response::Status {
+                total: self.spots.len(),
+                occupied: self.occupied_count(),
+                reserved: self.reserved_count(),
+                held: self.held_count(),
+                free: self.free_count(),
+            }),
+        };
+        result.unwrap_or_else(Response::Error)
+    }
+
+    fn spot_info(&self, id: usize) -> Result<SpotInfo, &'static str> {
+        let spot = self.spots.get(id).ok_or("Invalid spot ID")?;
+        Ok(SpotInfo {
+            id: spot.id,
+            status: spot.status(),
+            plate: spot.plate.clone(),
+            parked_at: spot.parked_at,
+            vip: spot.vip,
+            held_until: spot.held_until,
+            reservation: self.reservations.get(&id).cloned(),
+            confirmation_code: self.confirmation_codes.get(&id).cloned(),
+            claim_at: self.scheduled.get(&id).copied(),
+            payment_due: self.pending_payment.get(&id).copied(),
+            use_count: spot.use_count,
+        })
+    }
+
+    fn reserve_spot(&mut self, id: usize, details: String) -> Result<(), &'static str> {
+        self.ensure_open()?;
+        if id >= self.spots.len() {
+            return Err("Invalid spot ID");
+        }
+        let spot = &self.spots[id];
+        if spot.occupied || spot.reserved {
+            return Err("Spot already occupied or reserved");
+        }
+        if spot.held_until.is_some() {
+            return Err("Spot is held for another customer");
+        }
+        if self.reservation_quota_left() == 0 {
+            return Err("Reservation quota for the lot is full");
+        }
+        self.insert_reservation(id, details);
+        Ok(())
+    }
+
+    // Reserves a spot under the next sequential reference, e.g. "RES-0001".
+    fn reserve_spot_auto(&mut self, id: usize) -> Result<String, &'static str> {
+        let details = format!("RES-{:04}", self.auto_reservation_counter + 1);
+        self.reserve_spot(id, details.clone())?;
+        self.auto_reservation_counter += 1;
+        Ok(details)
+    }
+
+    // Reserves the spot if it is free. Otherwise returns the reason, and when the
+    // spot was taken or held, up to three available spots nearest to it, closest first.
+    fn reserve_or_suggest(&mut self, id: usize, details: String) -> Result<(), (&'static str, Vec<usize>)> {
+        let reason = match self.reserve_spot(id, details) {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        let taken = self.spots.get(id).is_some_and(|spot| !spot.is_available());
+        if self.maintenance_mode || !taken {
+            return Err((reason, Vec::new()));
+        }
+        let mut suggestions: Vec<usize> = self
+            .spots
+            .iter()
+            .filter(|spot| spot.is_available())
+            .map(|spot| spot.id)
+            .collect();
+        suggestions.sort_by_key(|&other| other.abs_diff(id));
+        suggestions.truncate(3);
+        Err((reason, suggestions))
+    }
+
+    // How many more reservations the lot-wide cap allows.
+    fn reservation_quota_left(&self) -> usize {
+        self.max_total_reservations
+            .map_or(usize::MAX, |max| max.saturating_sub(self.reservations.len()))
+    }
+
+    // Marks a spot reserved and issues the reservation a confirmation code.
+    fn insert_reservation(&mut self, id: usize, details: String) {
+        self.spots[id].touch();
+        self.spots[id].reserved = true;
+        self.reservations.insert(id, details);
+        self.reserved_at.insert(id, Instant::now());
+        let code = self.next_confirmation_code(id);
+        self.confirmation_codes.insert(id, code);
+    }
+
+    fn next_confirmation_code(&mut self, id: usize) -> String {
+        self.reservation_counter += 1;
+        // Mixing the counter with the spot ID keeps every code unique without an RNG.
+        format!("{:0>6}", to_base36((self.reservation_counter << 16) | id as u64))
+    }
+
+    fn confirmation_code(&self, id: usize) -> Option<&str> {
+        self.confirmation_codes.get(&id).map(String::as_str)
+    }
+
+    // Parks the car holding the reservation with this confirmation code and returns its spot ID.
+    fn claim_by_code(&mut self, code: &str, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        let id = self
+            .confirmation_codes
+            .iter()
+            .find(|(_, issued)| issued.eq_ignore_ascii_case(code.trim()))
+            .map(|(&id, _)| id)
+            .ok_or("No reservation with that confirmation code")?;
+        self.claim(id, Some(plate))?;
+        Ok(id)
+    }
+
+    // Briefly holds a free spot, e.g. while an online customer is on the way.
+    // A held spot cannot be parked in or reserved until the hold expires.
+    fn hold_spot(&mut self, id: usize, ttl: Duration) -> Result<(), &'static str> {
+        self.ensure_open()?;
+        let spot = self.spots.get_mut(id).ok_or("Invalid spot ID")?;
+        if !spot.is_available() {
+            return Err("Spot is not available");
+        }
+        let until = Instant::now().checked_add(ttl).ok_or("Hold is too long")?;
+        spot.touch();
+        spot.held_until = Some(until);
+        Ok(())
+    }
+
+    // Frees every hold whose TTL has passed and returns the released spot IDs.
+    fn release_expired_holds(&mut self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut released = Vec::new();
+        for spot in &mut self.spots {
+            if spot.held_until.is_some_and(|until| until <= now) {
+                spot.touch();
+                spot.held_until = None;
+                released.push(spot.id);
+            }
+        }
+        released
+    }
+
+    // Registers a handler that runs on every cancellation, manual, expired or overridden,
+    // e.g. to trigger a refund. Cancellations inside a transaction are only
+    // reported once it commits.
+    fn set_cancel_handler<F: FnMut(usize, &str) + 'static>(&mut self, handler: F) {
+        self.on_cancel = Some(Rc::new(RefCell::new(handler)));
+    }
+
+    // Cancels the reservation on a spot. If someone is waiting for the spot,
+    // their reservation takes over and its details are returned.
+    fn cancel_reservation(&mut self, id: usize) -> Result<Option<String>, &'static str> {
+        if id >= self.spots.len() || !self.spots[id].reserved {
+            return Err("Invalid spot ID or spot not reserved");
+        }
+        if let Some(details) = self.reservations.get(&id).cloned() {
+            self.report_cancellation(id, details);
+        }
+        self.clear_reservation(id);
+        Ok(self.promote_queued(id))
+    }
+
+    // Hands a cancellation to the cancel handler, or queues it while a
+    // transaction is running.
+    fn report_cancellation(&mut self, id: usize, details: String) {
+        match &mut self.queued_cancellations {
+            Some(queue) => queue.push((id, details)),
+            None => self.notify_cancel(id, &details),
+        }
+    }
+
+    fn notify_cancel(&self, id: usize, details: &str) {
+        if let Some(handler) = &self.on_cancel {
+            (handler.borrow_mut())(id, details);
+        }
+    }
+
+    // Reserves the first spot that automatic parking would pick, skipping VIP spots.
+    fn reserve_any(&mut self, details: String) -> Result<usize, &'static str> {
+        let id = self.find_available_spot().ok_or("No available spots")?.id;
+        self.reserve_spot(id, details)?;
+        Ok(id)
+    }
+
+    // Reserves every free spot matching `pred` under the same details and
+    // returns the reserved IDs. Occupied, reserved and held spots are skipped,
+    // and reserving stops once the lot-wide quota is used up.
+    fn reserve_where<F: Fn(&ParkingSpot) -> bool>(&mut self, pred: F, details: String) -> Vec<usize> {
+        if self.ensure_open().is_err() {
+            return Vec::new();
+        }
+        let ids: Vec<usize> = self
+            .spots
+            .iter()
+            .filter(|spot| spot.is_available() && pred(spot))
+            .map(|spot| spot.id)
+            .take(self.reservation_quota_left())
+            .collect();
+        for &id in &ids {
+            self.insert_reservation(id, details.clone());
+        }
+        ids
+    }
+
+    // Reserves each free spot whose ID is a multiple of `step`, e.g. every
+    // fifth spot for carpools, and returns the reserved IDs.
+    fn reserve_every(&mut self, step: usize, details: String) -> Result<Vec<usize>, &'static str> {
+        if step == 0 {
+            return Err("Step must be at least one");
+        }
+        self.ensure_open()?;
+        Ok(self.reserve_where(|spot| spot.id.is_multiple_of(step), details))
+    }
+
+    // Reserves the free spot that has been used the fewest times, ties going to the lowest ID.
+    fn reserve_least_used(&mut self, details: String) -> Result<usize, &'static str> {
+        let id = self
+            .spots
+            .iter()
+            .filter(|spot| spot.is_auto_assignable())
+            .min_by_key(|spot| (spot.use_count, spot.id))
+            .ok_or("No available spots")?
+            .id;
+        self.reserve_spot(id, details)?;
+        Ok(id)
+    }
+
+    // Reserves `count` spots under one name, preferring a contiguous block and
+    // falling back to the lowest free spots. Nothing is reserved unless all fit.
+    fn reserve_group(&mut self, name: String, count: usize) -> Result<Vec<usize>, &'static str> {
+        self.ensure_open()?;
+        if count == 0 {
+            return Err("Group size must be at least one");
+        }
+        let free: Vec<usize> = self.spots.iter().filter(|spot| spot.is_auto_assignable()).map(|spot| spot.id).collect();
+        if free.len() < count {
+            return Err("Not enough available spots for the group");
+        }
+        if self.reservation_quota_left() < count {
+            return Err("Reservation quota for the lot is full");
+        }
+        let ids: Vec<usize> = match free.windows(count).find(|run| run[count - 1] - run[0] == count - 1) {
+            Some(run) => run.to_vec(),
+            None => free[..count].to_vec(),
+        };
+        for &id in &ids {
+            self.reserve_spot(id, name.clone())?;
+        }
+        Ok(ids)
+    }
+
+    // Parks the holder of a reservation in their reserved spot. `name` must
+    // match the reservation details, ignoring case.
+    fn claim_reservation(&mut self, id: usize, name: &str) -> Result<(), &'static str> {
+        self.ensure_open()?;
+        let details = self.reservations.get(&id).ok_or("Invalid spot ID or spot not reserved")?;
+        if !details.eq_ignore_ascii_case(name) {
+            return Err("Reservation belongs to someone else");
+        }
+        self.claim(id, None)
+    }
+
+    fn claim(&mut self, id: usize, plate: Option<String>) -> Result<(), &'static str> {
+        self.ensure_open()?;
+        if !self.spots[id].reserved {
+            return Err("Spot is no longer reserved");
+        }
+        if self.spots[id].occupied {
+            return Err("Reserved spot is already occupied");
+        }
+        self.clear_reservation(id);
+        self.occupy_spot(id, plate);
+        self.promote_queued(id);
+        Ok(())
+    }
+
+    // Reserves the next free spot for a pre-registered plate, which becomes the
+    // reservation's identity. The plate is also stored as the details.
+    fn reserve_for_plate(&mut self, plate: String) -> Result<usize, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        if self.reserved_plates.values().any(|reserved| *reserved == plate) {
+            return Err("Plate already has a reservation");
+        }
+        let id = self.reserve_any(plate.clone())?;
+        self.reserved_plates.insert(id, plate);
+        Ok(id)
+    }
+
+    // Parks a pre-registered car in the spot reserved for its plate, ignoring
+    // case and punctuation.
+    fn claim_by_plate(&mut self, plate: &str) -> Result<usize, &'static str> {
+        let plate = normalize_plate(plate);
+        let (id, reserved) = self
+            .reserved_plates
+            .iter()
+            .find(|(_, reserved)| **reserved == plate)
+            .map(|(&id, reserved)| (id, reserved.clone()))
+            .ok_or("No reservation for that plate")?;
+        self.claim(id, Some(reserved))?;
+        Ok(id)
+    }
+
+    // Reserves a free spot and parks the customer's car in it straight away,
+    // for drive-up reservations. The spot stays reserved while occupied, and
+    // `remove_car` clears both when the car leaves, unless the reservation has
+    // been replaced in the meantime.
+    fn reserve_and_occupy(&mut self, id: usize, details: String, plate: String) -> Result<(), &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.reserve_spot(id, details)?;
+        self.occupy_spot(id, Some(plate));
+        self.drive_ups.insert(id);
+        Ok(())
+    }
+
+    // Parks a walk-in in a reserved spot whose holder did not show up. The
+    // reservation is cancelled and its details are returned for the operator's log.
+    fn park_override_reservation(&mut self, id: usize, plate: String) -> Result<String, &'static str> {
+        let plate = self.plate_for(&plate)?;
+        self.ensure_open()?;
+        let details = self.reservations.get(&id).cloned().ok_or("Invalid spot ID or spot not reserved")?;
+        if self.spots[id].occupied {
+            return Err("Reserved spot is already occupied");
+        }
+        self.report_cancellation(id, details.clone());
+        self.clear_reservation(id);
+        self.occupy_spot(id, Some(plate));
+        self.promote_queued(id);
+        Ok(details)
+    }
+
+    // Puts a reservation on the waitlist of an already reserved spot and
+    // returns its position in the queue.
+    fn queue_reservation(&mut self, id: usize, details: String) -> Result<usize, &'static str> {
+        self.ensure_open()?;
+        if id >= self.spots.len() {
+            return Err("Invalid spot ID");
+        }
+        if !self.spots[id].reserved {
+            return Err("Spot is not reserved, reserve it directly instead");
+        }
+        let queue = self.reservation_queues.entry(id).or_default();
+        queue.push_back(details);
+        Ok(queue.len())
+    }
+
+    // Drops every waitlisted reservation and returns how many there were.
+    fn clear_waitlists(&mut self) -> usize {
+        self.reservation_queues.drain().map(|(_, queue)| queue.len()).sum()
+    }
+
+    fn promote_queued(&mut self, id: usize) -> Option<String> {
+        let queue = self.reservation_queues.get_mut(&id)?;
+        let details = queue.pop_front()?;
+        if queue.is_empty() {
+            self.reservation_queues.remove(&id);
+        }
+        self.insert_reservation(id, details.clone());
+        Some(details)
+    }
+
+    // Cancels every reservation whose spot ID and details match `pred`,
+    // returning the cancelled spot IDs in ascending order.
+    fn cancel_reservations_where<F: Fn(usize, &str) -> bool>(&mut self, pred: F) -> Vec<usize> {
+        let mut ids: Vec<usize> = self
+            .reservations
+            .iter()
+            .filter(|(&id, details)| pred(id, details))
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        for &id in &ids {
+            // Only reserved spots have details, so cancelling cannot fail.
+            let _ = self.cancel_reservation(id);
+        }
+        ids
+    }
+
+    // Reserved spots nobody has claimed for longer than `threshold`, in ID
+    // order. Scheduled reservations are skipped since they are meant to wait.
+    fn idle_reservations(&self, threshold: Duration) -> Vec<usize> {
+        let mut ids: Vec<usize> = self
+            .reserved_at
+            .iter()
+            .filter(|(id, reserved_at)| {
+                let spot = &self.spots[**id];
+                spot.reserved && !spot.occupied && !self.scheduled.contains_key(id) && reserved_at.elapsed() > threshold
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    // Reservation names (compared case-insensitively) held on more than one
+    // spot, with their spot IDs in ascending order.
+    fn duplicate_reservation_names(&self) -> Vec<(String, Vec<usize>)> {
+        let mut by_name: HashMap<String, (String, Vec<usize>)> = HashMap::new();
+        let mut ids: Vec<&usize> = self.reservations.keys().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let name = &self.reservations[id];
+            by_name
+                .entry(name.to_lowercase())
+                .or_insert_with(|| (name.clone(), Vec::new()))
+                .1
+                .push(*id);
+        }
+        let mut duplicates: Vec<(String, Vec<usize>)> = by_name.into_values().filter(|(_, ids)| ids.len() > 1).collect();
+        duplicates.sort_by_key(|(name, _)| name.to_lowercase());
+        duplicates
+    }
+
+    // (name, spot ID) pairs sorted by name ignoring case, then by spot ID.
+    fn reservations_sorted_by_name(&self) -> Vec<(String, usize)> {
+        let mut sorted: Vec<(String, usize)> = self.reservations.iter().map(|(&id, name)| (name.clone(), id)).collect();
+        sorted.sort_by_key(|(name, id)| (name.to_lowercase(), *id));
+        sorted
+    }
+
+    // Drops a reservation and everything tracked alongside it.
+    fn clear_reservation(&mut self, id: usize) {
+        self.spots[id].touch();
+        self.spots[id].reserved = false;
+        self.reservations.remove(&id);
+        self.scheduled.remove(&id);
+        self.pending_payment.remove(&id);
+        self.confirmation_codes.remove(&id);
+        self.reserved_at.remove(&id);
+        self.reserved_plates.remove(&id);
+        self.drive_ups.remove(&id);
+    }
+
+    // Reserves a spot for online booking. The reservation stays pending until
+    // it is confirmed and is cancelled automatically once `payment_ttl` runs out.
+    fn reserve_pending(&mut self, id: usize, details: String, payment_ttl: Duration) -> Result<(), &'static str> {
+        let deadline = Instant::now().checked_add(payment_ttl).ok_or("Payment window is too long")?;
+        self.reserve_spot(id, details)?;
+        self.pending_payment.insert(id, deadline);
+        Ok(())
+    }
+
+    fn confirm_reservation(&mut self, id: usize) -> Result<(), &'static str> {
+        self.pending_payment
+            .remove(&id)
+            .map(|_| ())
+            .ok_or("Spot has no reservation pending payment")
+    }
+
+    // Cancels pending reservations whose payment deadline has passed and
+    // returns their spot IDs.
+    fn expire_pending_reservations(&mut self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut expired: Vec<usize> = self
+            .pending_payment
+            .iter()
+            .filter(|(_, &deadline)| deadline <= now)
+            .map(|(&id, _)| id)
+            .collect();
+        expired.sort_unstable();
+        for &id in &expired {
+            // The spot is known to be reserved, so cancelling cannot fail.
+            let _ = self.cancel_reservation(id);
+        }
+        expired
+    }
+
+    // Reserves a spot that is automatically claimed once `claim_at` is reached.
+    fn schedule_reservation(&mut self, id: usize, details: String, claim_at: Instant) -> Result<(), &'static str> {
+        self.reserve_spot(id, details)?;
+        self.scheduled.insert(id, claim_at);
+        Ok(())
+    }
+
+    // Turns every scheduled reservation that is due into an occupied spot and
+    // returns the IDs that were activated. A due spot that is already occupied
+    // keeps its reservation so the operator can sort out the conflict.
+    fn activate_scheduled(&mut self, now: Instant) -> Vec<usize> {
+        // Due reservations wait until the lot reopens.
+        if self.maintenance_mode {
+            return Vec::new();
+        }
+        let mut due: Vec<usize> = self
+            .scheduled
+            .iter()
+            .filter(|(_, &claim_at)| claim_at <= now)
+            .map(|(&id, _)| id)
+            .collect();
+        due.sort_unstable();
+
+        let mut activated = Vec::new();
+        for id in due {
+            self.scheduled.remove(&id);
+            let spot = &self.spots[id];
+            if spot.occupied || !spot.reserved {
+                continue;
+            }
+            self.clear_reservation(id);
+            self.occupy_spot(id, None);
+            self.promote_queued(id);
+            activated.push(id);
+        }
+        activated
+    }
+}
+
+// Canonical form of a license plate: uppercase letters and digits only, so
+// "ab 123", "AB123" and "ab-123" all refer to the same car.
+fn normalize_plate(plate: &str) -> String {
+    plate.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+// A count of minutes as a Duration, or None when it would overflow.
+fn minutes_to_duration(minutes: u64) -> Option<Duration> {
+    minutes.checked_mul(60).map(Duration::from_secs)
+}
+
+fn to_base36(mut value: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut digits = Vec::new();
+    loop {
+        digits.push(DIGITS[(value % 36) as usize]);
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().map(|&digit| digit as char).collect()
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_optional(value: Option<&str>) -> String {
+    value.map(json_string).unwrap_or_else(|| "null".to_string())
+}
+
+// Reads a flat JSON object whose values are strings or whole numbers, which
+// is all a `Request` needs. Numbers are kept as their text.
+fn parse_json_object(text: &str) -> Result<HashMap<String, String>, &'static str> {
+    const MALFORMED: &str = "Malformed JSON object";
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    if chars.next() != Some('{') {
+        return Err(MALFORMED);
+    }
+    skip_json_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_json_whitespace(&mut chars);
+            let key = parse_json_string(&mut chars)?;
+            skip_json_whitespace(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(MALFORMED);
+            }
+            skip_json_whitespace(&mut chars);
+            let value = if chars.peek() == Some(&'"') {
+                parse_json_string(&mut chars)?
+            } else {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                if digits.is_empty() {
+                    return Err(MALFORMED);
+                }
+                digits
+            };
+            fields.insert(key, value);
+            skip_json_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(MALFORMED),
+            }
+        }
+    }
+    if chars.next().is_some() {
+        return Err(MALFORMED);
+    }
+    Ok(fields)
+}
+
+fn skip_json_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+// Reads a quoted JSON string, undoing the escapes `json_string` writes.
+fn parse_json_string(chars: &mut Peekable<Chars>) -> Result<String, &'static str> {
+    const MALFORMED: &str = "Malformed JSON string";
+    if chars.next() != Some('"') {
+        return Err(MALFORMED);
+    }
+    let mut value = String::new();
+    loop {
+        match chars.next().ok_or(MALFORMED)? {
+            '"' => return Ok(value),
+            '\\' => match chars.next().ok_or(MALFORMED)? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).map_err(|_| MALFORMED)?;
+                    value.push(char::from_u32(code).ok_or(MALFORMED)?);
+                }
+                c @ ('"' | '\\' | '/') => value.push(c),
+                _ => return Err(MALFORMED),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+// Menu entries as (key, full label, short label for the compact menu).
+const MENU: &[(&str, &str, &str)] = &[
+    ("1", "Park car in next available spot", "Park"),
+    ("2", "Park car in specific spot", "Spot"),
+    ("3", "Remove car from spot", "Remove"),
+    ("4", "List all spots", "List"),
+    ("5", "Find nearest available spot", "Nearest"),
+    ("6", "Reserve a spot in advance", "Reserve"),
+    ("7", "Cancel a reservation", "Cancel"),
+    ("8", "Exit", "Exit"),
+    ("9", "Help", "Help"),
+    ("10", "Schedule a reservation", "Schedule"),
+    ("11", "Inspect spot", "Inspect"),
+    ("12", "Cancel reservations by name", "CancelName"),
+    ("13", "Cancel all reservations", "CancelAll"),
+    ("14", "Recently freed spots", "Freed"),
+    ("15", "List one spot", "ListOne"),
+    ("16", "Park car spread out", "Spread"),
+    ("17", "Park car in VIP spot", "VIP"),
+    ("18", "Parking duration report", "Stays"),
+    ("19", "Simulate arrivals", "Simulate"),
+    ("20", "List spots sorted", "Sorted"),
+    ("21", "Show spot as JSON", "JSON"),
+    ("22", "Toggle maintenance mode", "Maintenance"),
+    ("23", "Next available spot after", "NextAfter"),
+    ("24", "Spot usage report", "Usage"),
+    ("25", "Join reservation waitlist", "Waitlist"),
+    ("26", "Average walking distance", "Walk"),
+    ("27", "Hold a spot", "Hold"),
+    ("28", "Apply a batch of operations", "Batch"),
+    ("29", "Duplicate reservations", "Duplicates"),
+    ("30", "Show lot as grid", "Grid"),
+    ("31", "Reserve least used spot", "ReserveLeastUsed"),
+    ("32", "Find neighboring spots", "Neighbors"),
+    ("33", "Reserve spots for a group", "Group"),
+    ("34", "Park car from the middle", "Middle"),
+    ("35", "Spots due for maintenance", "Worn"),
+    ("36", "Reserve pending payment", "ReservePending"),
+    ("37", "Confirm reservation payment", "Confirm"),
+    ("38", "Longest parked cars", "Longest"),
+    ("39", "Remove several cars", "RemoveMany"),
+    ("40", "Park car in its usual spot", "Usual"),
+    ("41", "Take a snapshot", "Snapshot"),
+    ("42", "Compare with snapshot", "Diff"),
+    ("43", "Reserve any available spot", "ReserveAny"),
+    ("44", "Change list format", "Format"),
+    ("45", "Find nearest spot including reserved", "NearestAny"),
+    ("46", "Park car in longest vacant spot", "Vacant"),
+    ("47", "Reserve and park now", "DriveUp"),
+    ("48", "Reservations by name", "ByName"),
+    ("49", "Claim reservation by code", "ClaimCode"),
+    ("50", "Reserve all VIP spots", "ReserveVIP"),
+    ("51", "Compact parked cars", "Compact"),
+    ("52", "Spots changed since last check", "Changes"),
+    ("53", "Park in reserved spot (override)", "Override"),
+    ("54", "Print state snapshot", "Dump"),
+    ("55", "Occupied clusters", "Clusters"),
+    ("56", "Reserve with auto reference", "AutoRes"),
+    ("57", "Nearest to destination", "Dest"),
+    ("58", "Park a batch of cars", "Batch park"),
+    ("59", "Reserve or suggest", "Suggest"),
+    ("60", "Idle reservations", "Idle"),
+    ("61", "Fill order", "Fills"),
+    ("62", "Reserve every Nth spot", "Every Nth"),
+    ("63", "Reserve for a plate", "PlateRes"),
+    ("64", "Claim by plate", "PlateClaim"),
+    ("65", "Is a plate parked", "Parked?"),
+    ("66", "Session uptime", "Uptime"),
+    ("67", "Add an adjoining lot", "Merge"),
+    ("68", "Last known spot of a plate", "LastSpot"),
+    ("69", "Reserve when a spot frees up", "Deferred"),
+    ("70", "Status breakdown", "Breakdown"),
+    ("71", "Run a request", "Request"),
+    ("p", "Quick park", "Quick"),
+];
+
+fn display_help() {
+    say!("Parking Lot Help:");
+    say!("1. Park car in next available spot: Automatically parks your car in the next available spot.");
+    say!("2. Park car in specific spot: Allows you to choose a specific spot to park your car.");
+    say!("3. Remove car from spot: Removes the car from the specified spot.");
+    say!("4. List all spots: Displays the status of all parking spots (Occupied, Reserved, or Available).");
+    say!("5. Find nearest available spot: Finds the nearest available spot from your current position, or from the entrance if you just press enter.");
+    say!("6. Reserve a spot in advance: Allows you to reserve a parking spot for future use.");
+    say!("7. Cancel a reservation: Cancels an existing reservation for a spot.");
+    say!("8. Exit: Exits the parking lot system.");
+    say!("9. Help: Displays this help information.");
+    say!("10. Schedule a reservation: Reserves a spot that is automatically occupied after the given number of minutes.");
+    say!("11. Inspect spot: Shows everything known about a single spot.");
+    say!("12. Cancel reservations by name: Cancels every reservation whose details match the given name.");
+    say!("13. Cancel all reservations: Cancels every reservation in the lot and empties every waitlist.");
+    say!("14. Recently freed spots: Lists the spots that were freed most recently.");
+    say!("15. List one spot: Displays the status of a single parking spot.");
+    say!("16. Park car spread out: Parks in the free spot farthest from other cars, for easier door access.");
+    say!("17. Park car in VIP spot: Parks in a VIP spot, which is never assigned automatically.");
+    say!("18. Parking duration report: Shows the average and median length of completed stays, the session length and the turnover rate.");
+    say!("19. Simulate arrivals: Runs a number of reproducible pseudo-random park and remove operations.");
+    say!("20. List spots sorted: Lists all spots sorted by ID or grouped by status.");
+    say!("21. Show spot as JSON: Prints a single spot as a JSON object.");
+    say!("22. Toggle maintenance mode: Closes or reopens the lot. While closed, cars can leave but no new cars can park or reserve.");
+    say!("23. Next available spot after: Finds the first available spot at or after the given spot number.");
+    say!("24. Spot usage report: Lists the least used spots and how many times each has been occupied, plus a Gini coefficient of how evenly the spots are used.");
+    say!("25. Join reservation waitlist: Queues a reservation for an already reserved spot. It takes over when the current reservation is canceled.");
+    say!("26. Average walking distance: Shows the mean distance from a position (the entrance by default) to all occupied spots, and the total.");
+    say!("27. Hold a spot: Holds a spot for a few minutes so it cannot be parked in or reserved until the hold expires.");
+    say!("28. Apply a batch of operations: Applies several operations separated by ';' (park N, remove N, reserve N details, cancel N). If one fails, none are applied.");
+    say!("29. Duplicate reservations: Lists reservation names that hold more than one spot.");
+    say!("30. Show lot as grid: Draws the lot as a grid of status symbols (X occupied, R reserved, H held, . available).");
+    say!("31. Reserve least used spot: Reserves the available spot that has been used the fewest times.");
+    say!("32. Find neighboring spots: Lists the spots within a given distance of a spot and which of them are available.");
+    say!("33. Reserve spots for a group: Reserves several spots under one name, side by side when possible.");
+    say!("34. Park car from the middle: Parks in the available spot closest to the middle of the lot.");
+    say!("35. Spots due for maintenance: Lists spots used more than a given number of times, most used first.");
+    say!("36. Reserve pending payment: Reserves a spot for online booking. It is canceled automatically unless confirmed within the given minutes.");
+    say!("37. Confirm reservation payment: Confirms a reservation that is pending payment so it no longer expires.");
+    say!("38. Longest parked cars: Lists the cars that have been parked the longest, with their plates and how long they have stayed.");
+    say!("39. Remove several cars: Removes the cars from a comma-separated list of spots and reports which removals failed.");
+    say!("40. Park car in its usual spot: Parks a returning car in the spot it used last time, or the nearest available spot if that one is taken.");
+    say!("41. Take a snapshot: Remembers the current state of the lot for a later comparison.");
+    say!("42. Compare with snapshot: Lists the spots whose status changed since the last snapshot.");
+    say!("43. Reserve any available spot: Reserves the next available spot without choosing a spot number.");
+    say!("44. Change list format: Switches option 4 between the full list, a compact line of symbols and a grid.");
+    say!("45. Find nearest spot including reserved: Finds the nearest free spot even if it is reserved, and lets the reservation holder claim it.");
+    say!("46. Park car in longest vacant spot: Parks in the available spot that has been empty the longest, to even out wear.");
+    say!("47. Reserve and park now: Reserves a spot and parks the car in it immediately, for drive-up reservations.");
+    say!("48. Reservations by name: Lists all reservations sorted alphabetically by name.");
+    say!("49. Claim reservation by code: Parks a car in its reserved spot using the reservation's confirmation code.");
+    say!("50. Reserve all VIP spots: Reserves every free VIP spot under one name.");
+    say!("51. Compact parked cars: Moves parked cars into the lowest-numbered free spots to close gaps (valet lots).");
+    say!("52. Spots changed since last check: Lists the spots that changed since this option was last used.");
+    say!("53. Park in reserved spot (override): Parks a walk-in in a reserved spot and cancels the no-show reservation. Asks for confirmation.");
+    say!("54. Print state snapshot: Prints every field of every spot in a fixed format, e.g. for comparing lots.");
+    say!("55. Occupied clusters: Lists each run of consecutive occupied spots.");
+    say!("56. Reserve with auto reference: Reserves a spot under a generated RES-nnnn reference.");
+    say!("57. Nearest to destination: Finds the free spot closest to a destination such as an elevator.");
+    say!("58. Park a batch of cars: Parks several plates, separated by commas, in the next available spots.");
+    say!("59. Reserve or suggest: Reserves a spot, or offers up to three nearby free spots if it is taken.");
+    say!("60. Idle reservations: Lists reserved spots that have gone unclaimed for longer than a number of minutes.");
+    say!("61. Fill order: Prints the spots in the order cars were parked in them.");
+    say!("62. Reserve every Nth spot: Reserves each free spot whose number is a multiple of the step.");
+    say!("63. Reserve for a plate: Reserves the next free spot for a pre-registered license plate.");
+    say!("64. Claim by plate: Parks a pre-registered car in the spot reserved for its plate.");
+    say!("65. Is a plate parked: Checks whether a car with the given plate is in the lot.");
+    say!("66. Session uptime: Shows how long the current session has been running.");
+    say!("67. Add an adjoining lot: Merges a new lot of the given size onto the end of this one.");
+    say!("68. Last known spot of a plate: Shows where a plate was last parked, even after it left.");
+    say!("69. Reserve when a spot frees up: Reserves a spot for someone once the car in another spot leaves.");
+    say!("70. Status breakdown: Counts the spots in every status.");
+    say!("71. Run a request: Runs a text request such as \"reserve 3 Amy\" or its JSON form {{\"action\":\"reserve\",\"id\":3,\"details\":\"Amy\"}} and prints the JSON response.");
+    say!("p. Quick park: Parks in the next available spot and prints the spot and occupancy on one line.");
+    say!("Press enter without choosing an option to repeat the previous command.");
+}
+
+fn print_simulation(parking_lot: &ParkingLot, summary: &SimulationSummary) {
+    say!(
+        "Simulation finished: {} parks, {} removals, {} failed parks",
+        summary.parks, summary.removals, summary.failed_parks
+    );
+    say!(
+        "{} occupied, {} reserved, {} held, {} available",
+        parking_lot.occupied_count(),
+        parking_lot.reserved_count(),
+        parking_lot.held_count(),
+        parking_lot.free_count()
+    );
+}
+
+// Reports spots that were re-reserved from their waitlist after a cancel or claim.
+fn print_promotions(parking_lot: &ParkingLot, canceled: &[usize]) {
+    for id in canceled {
+        if let Some(details) = parking_lot.reservations.get(id) {
+            say!("Waitlisted reservation promoted: spot {} now reserved for {}", id, details);
+        }
+    }
+}
+
+fn print_deferred(parking_lot: &ParkingLot, fulfilled: &[usize]) {
+    for id in fulfilled {
+        if let Some(details) = parking_lot.reservations.get(id) {
+            say!("Deferred reservation fulfilled: spot {} now reserved for {}", id, details);
+        }
+    }
+}
+
+fn print_removal(parking_lot: &ParkingLot, id: usize, removal: &Removal) {
+    if removal.promoted.is_some() {
+        print_promotions(parking_lot, &[id]);
+    }
+    print_deferred(parking_lot, &removal.deferred);
+}
+
+// Reports a failed park, adding an estimated wait when the lot is full.
+fn print_park_error(parking_lot: &ParkingLot, err: &str) {
+    match parking_lot.estimated_wait() {
+        Some(wait) if parking_lot.free_count() == 0 => {
+            println!("Error: {} (estimated wait: about {} minutes)", err, wait.as_secs().div_ceil(60))
+        }
+        _ => println!("Error: {}", err),
+    }
+}
+
+fn prompt(message: &str) -> String {
+    say_inline!("{}", message);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
+
+// Reads the value following a command-line flag, exiting if it is missing or malformed.
+fn flag_value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    match args.next().and_then(|value| value.parse().ok()) {
+        Some(value) => value,
+        None => {
+            eprintln!("Missing or invalid value for {}", flag);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let mut parking_lot = ParkingLot::new(10);
+    parking_lot.set_cancel_handler(|id, details| debug!("Reservation on spot {} for {} cancelled", id, details));
+    let mut compact_menu = false;
+    let mut last_command: Option<String> = None;
+    let mut snapshot: Option<ParkingLot> = None;
+    let mut last_change_check = Instant::now();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--entrance" => {
+                if let Err(err) = parking_lot.set_entrance(flag_value(&mut args, "--entrance")) {
+                    eprintln!("Error: {}", err);
+                    process::exit(1);
+                }
+            }
+            "--recent" => parking_lot.set_freed_history_capacity(flag_value(&mut args, "--recent")),
+            "--vip" => {
+                let ids: String = flag_value(&mut args, "--vip");
+                for id in ids.split(',') {
+                    let result = match id.trim().parse() {
+                        Ok(id) => parking_lot.set_vip(id, true),
+                        Err(_) => Err("Invalid spot ID"),
+                    };
+                    if let Err(err) = result {
+                        eprintln!("Error: {} in --vip", err);
+                        process::exit(1);
+                    }
+                }
+            }
+            "--simulate" => {
+                let summary = parking_lot.simulate(flag_value(&mut args, "--simulate"), 1);
+                print_simulation(&parking_lot, &summary);
+            }
+            "--compact-menu" => compact_menu = true,
+            "--verbose" => VERBOSITY.store(2, Ordering::Relaxed),
+            "--quiet" => VERBOSITY.store(0, Ordering::Relaxed),
+            "--status" => {
+                println!("{}", parking_lot.status_line());
+                return;
+            }
+            "--is-parked" => {
+                // Exit status only, for gate scripts; earlier flags such as --simulate apply first.
+                let plate: String = flag_value(&mut args, "--is-parked");
+                process::exit(if parking_lot.is_parked(&plate) { 0 } else { 1 });
+            }
+            "--max-reservations" => parking_lot.max_total_reservations = Some(flag_value(&mut args, "--max-reservations")),
+            "--strict-plates" => parking_lot.strict_plates = true,
+            _ => {
+                eprintln!("Unknown option: {}", arg);
+                process::exit(1);
+            }
+        }
+    }
+
+    loop {
+        for id in parking_lot.activate_scheduled(Instant::now()) {
+            say!("Scheduled reservation activated: car parked in spot {}", id);
+            print_promotions(&parking_lot, &[id]);
+        }
+        let fulfilled = parking_lot.retry_deferred();
+        print_deferred(&parking_lot, &fulfilled);
+        for id in parking_lot.release_expired_holds() {
+            say!("Hold on spot {} expired, spot is available again", id);
+        }
+        let expired = parking_lot.expire_pending_reservations();
+        for id in &expired {
+            say!("Unpaid reservation for spot {} expired and was canceled", id);
+        }
+        print_promotions(&parking_lot, &expired);
+
+        if compact_menu {
+            let entries: Vec<String> = MENU.iter().map(|(key, _, short)| format!("[{}]{}", key, short)).collect();
+            say!("\n{}", entries.join(" "));
+        } else {
+            say!("\nParking Lot Menu:");
+            for (key, label, _) in MENU {
+                say!("{}. {}", key, label);
+            }
+        }
+        say_inline!("Choose an option: ");
+        io::stdout().flush().unwrap();
+
+        let mut choice = String::new();
+        // Stop at end of input instead of repeating the last command forever.
+        if io::stdin().read_line(&mut choice).unwrap() == 0 {
+            break;
+        }
+        let mut choice = choice.trim().to_string();
+        if choice.is_empty() {
+            match &last_command {
+                Some(last) => {
+                    if let Some((key, label, _)) = MENU.iter().find(|(key, _, _)| key.eq_ignore_ascii_case(last)) {
+                        say!("Repeating: {}. {}", key, label);
+                    }
+                    choice = last.clone();
+                }
+                None => continue,
+            }
+        }
+        if MENU.iter().any(|(key, _, _)| key.eq_ignore_ascii_case(&choice)) {
+            last_command = Some(choice.clone());
+        }
+
+        if choice.eq_ignore_ascii_case("p") {
+            debug!("Strategy: first available spot");
+            match parking_lot.park_car() {
+                Ok(id) => say!(
+                    "Spot {} | {}/{} occupied",
+                    id,
+                    parking_lot.occupied_count(),
+                    parking_lot.spots.len()
+                ),
+                Err(err) => print_park_error(&parking_lot, err),
+            }
+            continue;
+        }
+        let choice: u32 = match choice.parse() {
+            Ok(num) => num,
+            Err(_) => continue,
+        };
+
+        match choice {
+            1 => {
+                debug!("Strategy: first available spot");
+                match parking_lot.park_car() {
+                    Ok(id) => say!("Car parked in spot {}", id),
+                    Err(err) => print_park_error(&parking_lot, err),
+                }
+            }
+            2 => {
+                say_inline!("Enter the spot number where you want to park the car: ");
+                io::stdout().flush().unwrap();
+                let mut spot = String::new();
+                io::stdin().read_line(&mut spot).unwrap();
+                let spot: usize = match spot.trim().parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.park_car_in_spot(spot) {
+                    Ok(_) => say!("Car parked in spot {}", spot),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            3 => {
+                say_inline!("Enter the spot number to remove the car from: ");
+                io::stdout().flush().unwrap();
+                let mut spot = String::new();
+                io::stdin().read_line(&mut spot).unwrap();
+                let spot: usize = match spot.trim().parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.remove_car(spot) {
+                    Ok(removal) => {
+                        say!("Car removed from spot {}", spot);
+                        print_removal(&parking_lot, spot, &removal);
+                    }
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
             4 => {
-                println!("Parking lot status:");
+                say!("Parking lot status:");
                 parking_lot.list_spots();
+                say!(
+                    "{} occupied, {} reserved, {} held, {} available ({:.0}% full)",
+                    parking_lot.occupied_count(),
+                    parking_lot.reserved_count(),
+                    parking_lot.held_count(),
+                    parking_lot.free_count(),
+                    parking_lot.occupancy_ratio() * 100.0
+                );
+            }
+            5 => {
+                say_inline!("Enter your current position (or press enter to search from the entrance): ");
+                io::stdout().flush().unwrap();
+                let mut position = String::new();
+                io::stdin().read_line(&mut position).unwrap();
+                let nearest = if position.trim().is_empty() {
+                    debug!("Strategy: nearest available spot from the entrance ({})", parking_lot.entrance);
+                    parking_lot.find_nearest_from_entrance()
+                } else {
+                    let position: usize = match position.trim().parse() {
+                        Ok(num) => num,
+                        Err(_) => {
+                            println!("Invalid input. Please enter a valid position.");
+                            continue;
+                        },
+                    };
+                    debug!("Strategy: nearest available spot from position {}", position);
+                    parking_lot.find_nearest_available_spot(position)
+                };
+
+                match nearest {
+                    Some(spot) => say!("Nearest available spot is {}", spot.id),
+                    None => say!("No available spots"),
+                }
+            }
+            6 => {
+                say_inline!("Enter the spot number to reserve: ");
+                io::stdout().flush().unwrap();
+                let mut spot = String::new();
+                io::stdin().read_line(&mut spot).unwrap();
+                let spot: usize = match spot.trim().parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                say_inline!("Enter reservation details: ");
+                io::stdout().flush().unwrap();
+                let mut details = String::new();
+                io::stdin().read_line(&mut details).unwrap();
+                let details = details.trim().to_string();
+
+                match parking_lot.reserve_spot(spot, details) {
+                    Ok(_) => say!(
+                        "Spot {} reserved, confirmation code {}",
+                        spot,
+                        parking_lot.confirmation_code(spot).unwrap_or_default()
+                    ),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            7 => {
+                say_inline!("Enter the spot number to cancel the reservation: ");
+                io::stdout().flush().unwrap();
+                let mut spot = String::new();
+                io::stdin().read_line(&mut spot).unwrap();
+                let spot: usize = match spot.trim().parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.cancel_reservation(spot) {
+                    Ok(promoted) => {
+                        say!("Reservation for spot {} canceled", spot);
+                        if let Some(details) = promoted {
+                            say!("Waitlisted reservation promoted: spot {} now reserved for {}", spot, details);
+                        }
+                    }
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            8 => {
+                say!("Exiting...");
+                break;
+            }
+            9 => {
+                display_help();
+            }
+            10 => {
+                let spot: usize = match prompt("Enter the spot number to reserve: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+                let minutes: u64 = match prompt("Enter minutes until the car arrives: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number of minutes.");
+                        continue;
+                    },
+                };
+
+                let claim_at = match minutes_to_duration(minutes).and_then(|delay| Instant::now().checked_add(delay)) {
+                    Some(at) => at,
+                    None => {
+                        println!("Invalid input. That many minutes is out of range.");
+                        continue;
+                    }
+                };
+                match parking_lot.schedule_reservation(spot, details, claim_at) {
+                    Ok(_) => say!("Spot {} reserved, it will be occupied in {} minutes", spot, minutes),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            11 => {
+                let spot: usize = match prompt("Enter the spot number to inspect: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.spot_info(spot) {
+                    Ok(info) => {
+                        say!("Spot {}", info.id);
+                        say!("  Status: {}", info.status);
+                        if let Some(plate) = info.plate {
+                            say!("  Plate: {}", plate);
+                        }
+                        if let Some(parked_at) = info.parked_at {
+                            say!("  Parked for: {} minutes", parked_at.elapsed().as_secs() / 60);
+                        }
+                        if info.vip {
+                            say!("  VIP spot");
+                        }
+                        if let Some(held_until) = info.held_until {
+                            let remaining = held_until.saturating_duration_since(Instant::now());
+                            say!("  Held for another: {} minutes", remaining.as_secs() / 60);
+                        }
+                        if let Some(details) = info.reservation {
+                            say!("  Reservation: {}", details);
+                        }
+                        if let Some(code) = info.confirmation_code {
+                            say!("  Confirmation code: {}", code);
+                        }
+                        if let Some(claim_at) = info.claim_at {
+                            let remaining = claim_at.saturating_duration_since(Instant::now());
+                            say!("  Scheduled claim in: {} minutes", remaining.as_secs() / 60);
+                        }
+                        if let Some(payment_due) = info.payment_due {
+                            let remaining = payment_due.saturating_duration_since(Instant::now());
+                            say!("  Pending payment, expires in: {} minutes", remaining.as_secs() / 60);
+                        }
+                        say!("  Times used: {}", info.use_count);
+                    }
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            12 => {
+                let name = prompt("Enter the reservation name to cancel: ");
+                let canceled = parking_lot.cancel_reservations_where(|_, details| details.eq_ignore_ascii_case(&name));
+                if canceled.is_empty() {
+                    say!("No reservations found for {}", name);
+                } else {
+                    say!("Canceled reservations for spots {:?}", canceled);
+                }
+                print_promotions(&parking_lot, &canceled);
+            }
+            13 => {
+                // Waitlists go first, otherwise each cancel would promote the next in line.
+                let waitlisted = parking_lot.clear_waitlists();
+                let canceled = parking_lot.cancel_reservations_where(|_, _| true);
+                say!("Canceled {} reservations and {} waitlisted requests", canceled.len(), waitlisted);
+            }
+            14 => {
+                let freed = parking_lot.recently_freed();
+                if freed.is_empty() {
+                    say!("No spots have been freed yet");
+                }
+                for (id, ago) in freed {
+                    say!("Spot {} freed {} seconds ago", id, ago.as_secs());
+                }
+            }
+            15 => {
+                let spot: usize = match prompt("Enter the spot number to list: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.list_spot(spot) {
+                    Ok(line) => say!("{}", line),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            16 => {
+                let plate = prompt("Enter the license plate: ");
+                debug!("Strategy: spread out, farthest from occupied spots");
+                match parking_lot.park_spread(plate) {
+                    Ok(id) => say!("Car parked in spot {}", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            17 => {
+                let spot: usize = match prompt("Enter the VIP spot number: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let plate = prompt("Enter the license plate: ");
+
+                match parking_lot.park_vip(spot, plate) {
+                    Ok(_) => say!("Car parked in VIP spot {}", spot),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            18 => {
+                match (parking_lot.average_stay(), parking_lot.median_stay()) {
+                    (Some(average), Some(median)) => {
+                        say!("Average stay: {} seconds", average.as_secs());
+                        say!("Median stay: {} seconds", median.as_secs());
+                    }
+                    _ => say!("No completed stays yet"),
+                }
+                say!("Session running for {} minutes", parking_lot.session_duration(Instant::now()).as_secs() / 60);
+                say!("Turnover: {:.2} cars per spot per hour", parking_lot.turnover_rate(Instant::now()));
+            }
+            19 => {
+                let steps: usize = match prompt("Enter the number of steps: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+                let seed: u64 = match prompt("Enter the seed: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                debug!("Simulating {} steps with seed {}", steps, seed);
+                let summary = parking_lot.simulate(steps, seed);
+                print_simulation(&parking_lot, &summary);
+            }
+            20 => {
+                say!("Sort by:");
+                say!("1. ID");
+                say!("2. Status");
+                let by = match prompt("Choose a sort order: ").as_str() {
+                    "1" => SortKey::Id,
+                    "2" => SortKey::Status,
+                    _ => {
+                        println!("Invalid choice. Please choose a valid sort order.");
+                        continue;
+                    }
+                };
+                parking_lot.list_spots_sorted(by);
+            }
+            21 => {
+                let spot: usize = match prompt("Enter the spot number: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.spot_to_json(spot) {
+                    Ok(json) => say!("{}", json),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            22 => {
+                let on = !parking_lot.maintenance_mode;
+                parking_lot.set_maintenance(on);
+                if on {
+                    say!("Maintenance mode on: the lot is closed for parking");
+                } else {
+                    say!("Maintenance mode off: the lot is open");
+                }
+            }
+            23 => {
+                let spot: usize = match prompt("Enter the spot number to search from: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.next_available_after(spot) {
+                    Some(id) => say!("Next available spot is {}", id),
+                    None => say!("No available spots at or after spot {}", spot),
+                }
+            }
+            24 => {
+                let count: usize = match prompt("How many spots to show: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                for (id, uses) in parking_lot.least_used(count) {
+                    say!("Spot {}: used {} times", id, uses);
+                }
+                say!("Usage Gini coefficient: {:.2}", parking_lot.usage_gini());
+            }
+            25 => {
+                let spot: usize = match prompt("Enter the reserved spot number: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+
+                match parking_lot.queue_reservation(spot, details) {
+                    Ok(position) => say!("Added to the waitlist for spot {} at position {}", spot, position),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            26 => {
+                let input = prompt("Enter the position to measure from (or press enter for the entrance): ");
+                let position: usize = if input.is_empty() {
+                    parking_lot.entrance
+                } else {
+                    match input.parse() {
+                        Ok(num) => num,
+                        Err(_) => {
+                            println!("Invalid input. Please enter a valid position.");
+                            continue;
+                        },
+                    }
+                };
+
+                match parking_lot.average_fill_distance(position) {
+                    Some(distance) => {
+                        say!("Average distance from {} to occupied spots: {:.2}", position, distance);
+                        say!("Total walking distance: {}", parking_lot.total_walk_distance(position));
+                    }
+                    None => say!("No occupied spots"),
+                }
+            }
+            27 => {
+                let spot: usize = match prompt("Enter the spot number to hold: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let minutes: u64 = match prompt("Enter how many minutes to hold it: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number of minutes.");
+                        continue;
+                    },
+                };
+
+                let Some(ttl) = minutes_to_duration(minutes) else {
+                    println!("Invalid input. That many minutes is out of range.");
+                    continue;
+                };
+                match parking_lot.hold_spot(spot, ttl) {
+                    Ok(_) => say!("Spot {} held for {} minutes", spot, minutes),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            28 => {
+                let input = prompt("Enter operations separated by ';': ");
+                let ops: Result<Vec<ParkingOp>, _> = input.split(';').filter(|op| !op.trim().is_empty()).map(str::parse).collect();
+                let ops = match ops {
+                    Ok(ops) => ops,
+                    Err(err) => {
+                        println!("Error: {}", err);
+                        continue;
+                    }
+                };
+
+                match parking_lot.apply_transaction(&ops) {
+                    Ok(_) => say!("Applied {} operations", ops.len()),
+                    Err(err) => println!("Error: {}. No operations were applied", err),
+                }
+            }
+            29 => {
+                let duplicates = parking_lot.duplicate_reservation_names();
+                if duplicates.is_empty() {
+                    say!("No duplicate reservations");
+                }
+                for (name, ids) in duplicates {
+                    say!("{}: spots {:?}", name, ids);
+                }
+            }
+            30 => {
+                let columns: usize = match prompt("Enter the number of columns: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+                if columns == 0 {
+                    println!("The grid needs at least one column.");
+                    continue;
+                }
+                say_inline!("{}", parking_lot.render_grid(columns));
+            }
+            31 => {
+                let details = prompt("Enter reservation details: ");
+                debug!("Strategy: least used available spot");
+                match parking_lot.reserve_least_used(details) {
+                    Ok(id) => say!("Spot {} reserved", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            32 => {
+                let spot: usize = match prompt("Enter the anchor spot number: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let radius: usize = match prompt("Enter the radius: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                if spot >= parking_lot.spots.len() {
+                    println!("Error: Invalid spot ID");
+                    continue;
+                }
+                say!("Neighbors of spot {}: {:?}", spot, parking_lot.neighbors(spot, radius));
+                say!("Available neighbors: {:?}", parking_lot.available_neighbors(spot, radius));
+            }
+            33 => {
+                let name = prompt("Enter the group name: ");
+                let count: usize = match prompt("Enter the number of cars: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.reserve_group(name, count) {
+                    Ok(ids) => say!("Reserved spots {:?}", ids),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            34 => {
+                let plate = prompt("Enter the license plate: ");
+                debug!("Strategy: middle of the lot outward");
+                match parking_lot.park_middle(plate) {
+                    Ok(id) => say!("Car parked in spot {}", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            35 => {
+                let threshold: u32 = match prompt("Enter the use threshold: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                let worn = parking_lot.spots_over_uses(threshold);
+                if worn.is_empty() {
+                    say!("No spots used more than {} times", threshold);
+                }
+                for id in worn {
+                    say!("Spot {}: used {} times", id, parking_lot.spots[id].use_count);
+                }
+            }
+            36 => {
+                let spot: usize = match prompt("Enter the spot number to reserve: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+                let minutes: u64 = match prompt("Enter minutes allowed for payment: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number of minutes.");
+                        continue;
+                    },
+                };
+
+                let Some(payment_ttl) = minutes_to_duration(minutes) else {
+                    println!("Invalid input. That many minutes is out of range.");
+                    continue;
+                };
+                match parking_lot.reserve_pending(spot, details, payment_ttl) {
+                    Ok(_) => say!("Spot {} reserved, awaiting payment within {} minutes", spot, minutes),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            37 => {
+                let spot: usize = match prompt("Enter the spot number to confirm: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+
+                match parking_lot.confirm_reservation(spot) {
+                    Ok(_) => say!("Reservation for spot {} confirmed", spot),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            38 => {
+                let count: usize = match prompt("How many cars to show: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+
+                let parked = parking_lot.longest_parked(count);
+                if parked.is_empty() {
+                    say!("No cars parked");
+                }
+                for (id, elapsed) in parked {
+                    let plate = parking_lot.spots[id].plate.as_deref().unwrap_or("unknown plate");
+                    say!("Spot {} ({}): parked {} minutes", id, plate, elapsed.as_secs() / 60);
+                }
+            }
+            39 => {
+                let input = prompt("Enter the spot numbers separated by commas: ");
+                let ids: Result<Vec<usize>, _> = input.split(',').map(|id| id.trim().parse()).collect();
+                let ids = match ids {
+                    Ok(ids) => ids,
+                    Err(_) => {
+                        println!("Invalid input. Please enter spot numbers separated by commas.");
+                        continue;
+                    }
+                };
+
+                let results = parking_lot.remove_cars(&ids);
+                let removed = results.iter().filter(|result| result.is_ok()).count();
+                say!("Removed {} of {} cars", removed, ids.len());
+                for (id, result) in ids.iter().zip(results) {
+                    match result {
+                        Ok(removal) => print_removal(&parking_lot, *id, &removal),
+                        Err(err) => println!("Error: spot {}: {}", id, err),
+                    }
+                }
             }
-            5 => {
-                print!("Enter your current position: ");
-                io::stdout().flush().unwrap();
-                let mut position = String::new();
-                io::stdin().read_line(&mut position).unwrap();
-                let position: usize = match position.trim().parse() {
+            40 => {
+                let plate = prompt("Enter the license plate: ");
+                debug!("Strategy: last spot used by {}, falling back to nearest from the entrance", plate);
+                match parking_lot.park_preferred(plate) {
+                    Ok(id) => say!("Car parked in spot {}", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            41 => {
+                snapshot = Some(parking_lot.clone());
+                say!("Snapshot taken");
+            }
+            42 => {
+                let Some(before) = &snapshot else {
+                    println!("Error: No snapshot taken yet");
+                    continue;
+                };
+                let changes = before.diff(&parking_lot);
+                if changes.is_empty() {
+                    say!("No changes since the snapshot");
+                }
+                for change in changes {
+                    say!("Spot {}: {} -> {}", change.id, change.before, change.after);
+                }
+            }
+            43 => {
+                let details = prompt("Enter reservation details: ");
+                match parking_lot.reserve_any(details) {
+                    Ok(id) => say!(
+                        "Spot {} reserved, confirmation code {}",
+                        id,
+                        parking_lot.confirmation_code(id).unwrap_or_default()
+                    ),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            44 => {
+                parking_lot.list_format = parking_lot.list_format.next();
+                match parking_lot.list_format {
+                    ListFormat::Verbose => say!("Spots will be listed one per line"),
+                    ListFormat::Compact => say!("Spots will be listed as a single line of symbols"),
+                    ListFormat::Grid(columns) => say!("Spots will be listed as a grid with {} columns", columns),
+                }
+            }
+            45 => {
+                let position: usize = match prompt("Enter your current position: ").parse() {
                     Ok(num) => num,
                     Err(_) => {
                         println!("Invalid input. Please enter a valid position.");
@@ -223,63 +2959,924 @@ fn main() {
                     },
                 };
 
-                match parking_lot.find_nearest_available_spot(position) {
-                    Some(spot) => println!("Nearest available spot is {}", spot.id),
-                    None => println!("No available spots"),
+                let (id, reserved) = match parking_lot.find_nearest_including_reserved(position) {
+                    Some((spot, reserved)) => (spot.id, reserved),
+                    None => {
+                        say!("No available spots");
+                        continue;
+                    }
+                };
+                if !reserved {
+                    say!("Nearest available spot is {}", id);
+                    continue;
+                }
+
+                say!("Nearest spot is {}, but it is reserved", id);
+                let name = prompt("If the reservation is yours, enter its name (or press enter to skip): ");
+                if name.is_empty() {
+                    continue;
+                }
+                match parking_lot.claim_reservation(id, &name) {
+                    Ok(_) => {
+                        say!("Reservation claimed, car parked in spot {}", id);
+                        print_promotions(&parking_lot, &[id]);
+                    }
+                    Err(err) => println!("Error: {}", err),
                 }
             }
-            6 => {
-                print!("Enter the spot number to reserve: ");
-                io::stdout().flush().unwrap();
-                let mut spot = String::new();
-                io::stdin().read_line(&mut spot).unwrap();
-                let spot: usize = match spot.trim().parse() {
+            46 => {
+                let plate = prompt("Enter the license plate: ");
+                debug!("Strategy: longest vacant spot");
+                match parking_lot.park_longest_vacant(plate) {
+                    Ok(id) => say!("Car parked in spot {}", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            47 => {
+                let spot: usize = match prompt("Enter the spot number: ").parse() {
                     Ok(num) => num,
                     Err(_) => {
                         println!("Invalid input. Please enter a valid spot number.");
                         continue;
                     },
                 };
+                let details = prompt("Enter reservation details: ");
+                let plate = prompt("Enter the license plate: ");
 
-                print!("Enter reservation details: ");
-                io::stdout().flush().unwrap();
-                let mut details = String::new();
-                io::stdin().read_line(&mut details).unwrap();
-                let details = details.trim().to_string();
-
-                match parking_lot.reserve_spot(spot, details) {
-                    Ok(_) => println!("Spot {} reserved", spot),
+                match parking_lot.reserve_and_occupy(spot, details, plate) {
+                    Ok(_) => say!("Spot {} reserved and occupied", spot),
                     Err(err) => println!("Error: {}", err),
                 }
             }
-            7 => {
-                print!("Enter the spot number to cancel the reservation: ");
-                io::stdout().flush().unwrap();
-                let mut spot = String::new();
-                io::stdin().read_line(&mut spot).unwrap();
-                let spot: usize = match spot.trim().parse() {
+            48 => {
+                let sorted = parking_lot.reservations_sorted_by_name();
+                if sorted.is_empty() {
+                    say!("No reservations");
+                }
+                for (name, id) in sorted {
+                    say!("{}: spot {}", name, id);
+                }
+            }
+            49 => {
+                let code = prompt("Enter the confirmation code: ");
+                let plate = prompt("Enter the license plate: ");
+                match parking_lot.claim_by_code(&code, plate) {
+                    Ok(id) => {
+                        say!("Reservation claimed, car parked in spot {}", id);
+                        print_promotions(&parking_lot, &[id]);
+                    }
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            50 => {
+                let details = prompt("Enter reservation details: ");
+                if parking_lot.maintenance_mode {
+                    println!("Error: Lot is closed for maintenance");
+                    continue;
+                }
+                let reserved = parking_lot.reserve_where(|spot| spot.vip, details);
+                if reserved.is_empty() {
+                    say!("No free VIP spots to reserve");
+                } else {
+                    say!("Reserved VIP spots {:?}", reserved);
+                }
+            }
+            51 => {
+                if !prompt("Move cars to close gaps? (y/N): ").eq_ignore_ascii_case("y") {
+                    say!("Nothing moved");
+                    continue;
+                }
+                let moves = parking_lot.compact();
+                if moves.is_empty() {
+                    say!("No gaps to close");
+                }
+                for (from, to) in moves {
+                    say!("Moved car from spot {} to spot {}", from, to);
+                }
+            }
+            52 => {
+                let changed = parking_lot.modified_since(last_change_check);
+                last_change_check = Instant::now();
+                if changed.is_empty() {
+                    say!("No spots changed since the last check");
+                }
+                for id in changed {
+                    say!("{}", parking_lot.spots[id]);
+                }
+            }
+            53 => {
+                let spot: usize = match prompt("Enter the reserved spot number: ").parse() {
                     Ok(num) => num,
                     Err(_) => {
                         println!("Invalid input. Please enter a valid spot number.");
                         continue;
                     },
                 };
+                let plate = prompt("Enter the license plate: ");
+                if !prompt("This cancels the existing reservation. Continue? (y/N): ").eq_ignore_ascii_case("y") {
+                    say!("Nothing changed");
+                    continue;
+                }
 
-                match parking_lot.cancel_reservation(spot) {
-                    Ok(_) => println!("Reservation for spot {} canceled", spot),
+                match parking_lot.park_override_reservation(spot, plate) {
+                    Ok(details) => {
+                        say!("Car parked in spot {}, canceled reservation: {}", spot, details);
+                        print_promotions(&parking_lot, &[spot]);
+                    }
                     Err(err) => println!("Error: {}", err),
                 }
             }
-            8 => {
-                println!("Exiting...");
-                break;
+            54 => {
+                say_inline!("{}", parking_lot.snapshot_string());
             }
-            9 => {
-                display_help();
+            55 => {
+                let clusters = parking_lot.occupied_clusters();
+                if clusters.is_empty() {
+                    say!("No occupied spots");
+                }
+                for (start, len) in clusters {
+                    say!("Spots {}-{}: {} car{}", start, start + len - 1, len, if len == 1 { "" } else { "s" });
+                }
+            }
+            56 => {
+                let spot: usize = match prompt("Enter the spot number to reserve: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                match parking_lot.reserve_spot_auto(spot) {
+                    Ok(details) => say!("Spot {} reserved as {}", spot, details),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            57 => {
+                let destination: usize = match prompt("Enter the destination spot number: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid position.");
+                        continue;
+                    },
+                };
+                debug!("Strategy: nearest available spot to destination {}", destination);
+                match parking_lot.find_nearest_to_destination(destination) {
+                    Some(spot) => say!("Nearest available spot to {} is {}", destination, spot.id),
+                    None => say!("No available spots"),
+                }
+            }
+            58 => {
+                let input = prompt("Enter the license plates separated by commas: ");
+                let plates: Vec<String> = input
+                    .split(',')
+                    .map(|plate| plate.trim().to_string())
+                    .filter(|plate| !plate.is_empty())
+                    .collect();
+                let count = plates.len();
+                let results = parking_lot.park_batch(plates);
+                let parked = results.iter().filter(|result| result.is_ok()).count();
+                say!("Parked {} of {} cars", parked, count);
+                if let Some(Err(err)) = results.iter().find(|result| result.is_err()) {
+                    println!("Error: {}", err);
+                }
+            }
+            59 => {
+                let spot: usize = match prompt("Enter the spot number to reserve: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+                let (reason, suggestions) = match parking_lot.reserve_or_suggest(spot, details.clone()) {
+                    Ok(()) => {
+                        say!(
+                            "Spot {} reserved, confirmation code {}",
+                            spot,
+                            parking_lot.confirmation_code(spot).unwrap_or_default()
+                        );
+                        continue;
+                    }
+                    Err(failure) => failure,
+                };
+                if suggestions.is_empty() {
+                    println!("Error: {}", reason);
+                    continue;
+                }
+                say!("Spot {} is not available. Nearby free spots: {:?}", spot, suggestions);
+                let choice = prompt("Pick one of these (or press enter to skip): ");
+                if choice.is_empty() {
+                    say!("Nothing reserved");
+                    continue;
+                }
+                match choice.parse::<usize>() {
+                    Ok(pick) if suggestions.contains(&pick) => match parking_lot.reserve_spot(pick, details) {
+                        Ok(_) => say!(
+                            "Spot {} reserved, confirmation code {}",
+                            pick,
+                            parking_lot.confirmation_code(pick).unwrap_or_default()
+                        ),
+                        Err(err) => println!("Error: {}", err),
+                    },
+                    _ => println!("Invalid choice. Please pick one of the suggested spots."),
+                }
+            }
+            60 => {
+                let minutes: u64 = match prompt("Enter the idle threshold in minutes: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number of minutes.");
+                        continue;
+                    },
+                };
+                // Only a threshold, so an out-of-range value simply matches nothing.
+                let threshold = minutes_to_duration(minutes).unwrap_or(Duration::MAX);
+                let idle = parking_lot.idle_reservations(threshold);
+                if idle.is_empty() {
+                    say!("No reservations idle for more than {} minutes", minutes);
+                }
+                for id in idle {
+                    say!(
+                        "Spot {} reserved for {} is unclaimed, consider cancelling",
+                        id,
+                        parking_lot.reservations.get(&id).map_or("", String::as_str)
+                    );
+                }
+            }
+            61 => {
+                let order = parking_lot.fill_order();
+                if order.is_empty() {
+                    say!("No cars parked yet");
+                } else {
+                    say!("Fill order: {:?}", order);
+                }
+            }
+            62 => {
+                let step: usize = match prompt("Enter the step: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+                match parking_lot.reserve_every(step, details) {
+                    Ok(reserved) if reserved.is_empty() => say!("No free spots at that interval"),
+                    Ok(reserved) => say!("Reserved spots {:?}", reserved),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            63 => {
+                let plate = prompt("Enter the license plate: ");
+                match parking_lot.reserve_for_plate(plate) {
+                    Ok(id) => say!("Spot {} reserved for the plate", id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            64 => {
+                let plate = prompt("Enter the license plate: ");
+                match parking_lot.claim_by_plate(&plate) {
+                    Ok(id) => {
+                        say!("Car parked in reserved spot {}", id);
+                        print_promotions(&parking_lot, &[id]);
+                    }
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            65 => {
+                let plate = prompt("Enter the license plate: ");
+                if parking_lot.is_parked(&plate) {
+                    say!("{} is parked in the lot", plate);
+                } else {
+                    say!("{} is not in the lot", plate);
+                }
+            }
+            66 => {
+                let elapsed = parking_lot.session_duration(Instant::now()).as_secs();
+                say!("Session running for {}h {}m {}s", elapsed / 3600, elapsed / 60 % 60, elapsed % 60);
+            }
+            67 => {
+                let size: usize = match prompt("Enter the number of spots in the adjoining lot: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a whole number.");
+                        continue;
+                    },
+                };
+                if size == 0 {
+                    say!("Nothing to add");
+                    continue;
+                }
+                let offset = parking_lot.spots.len();
+                match parking_lot.merge(ParkingLot::new(size), offset) {
+                    Ok(()) => say!("Added spots {}-{}, the lot now has {} spots", offset, offset + size - 1, parking_lot.spots.len()),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            68 => {
+                let plate = prompt("Enter the license plate: ");
+                match parking_lot.last_known_spot(&plate) {
+                    Some(id) if parking_lot.is_parked(&plate) => say!("{} is parked in spot {}", plate, id),
+                    Some(id) => say!("{} was last parked in spot {}", plate, id),
+                    None => say!("{} has not parked here", plate),
+                }
+            }
+            69 => {
+                let watch_id: usize = match prompt("Enter the spot number to wait for: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let reserve_id: usize = match prompt("Enter the spot number to reserve once it frees: ").parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Invalid input. Please enter a valid spot number.");
+                        continue;
+                    },
+                };
+                let details = prompt("Enter reservation details: ");
+                match parking_lot.reserve_on_free(watch_id, reserve_id, details) {
+                    Ok(()) => say!("Spot {} will be reserved once spot {} is free", reserve_id, watch_id),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            70 => {
+                let mut breakdown: Vec<(&str, usize)> = parking_lot.status_breakdown().into_iter().collect();
+                breakdown.sort_unstable();
+                for (status, count) in breakdown {
+                    say!("{:<20} {}", status, count);
+                }
+                say!("{:<20} {}", "Total", parking_lot.spots.len());
+            }
+            71 => {
+                let input = prompt("Enter the request: ");
+                let parsed = if input.starts_with('{') { Request::from_json(&input) } else { input.parse() };
+                let request = match parsed {
+                    Ok(request) => request,
+                    Err(err) => {
+                        println!("Invalid request: {}", err);
+                        continue;
+                    }
+                };
+                debug!("Request: {}", request.to_json());
+                say!("{}", parking_lot.handle_request(request).to_json());
             }
             _ => {
                 println!("Invalid choice. Please choose a valid option.");
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_sum_to_total() {
+        let mut lot = ParkingLot::new(6);
+        lot.park_car().unwrap();
+        lot.park_car().unwrap();
+        lot.reserve_spot(4, "amy".to_string()).unwrap();
+        lot.hold_spot(3, Duration::from_secs(60)).unwrap();
+        lot.reserve_and_occupy(5, "drive-up".to_string(), "AB123".to_string()).unwrap();
+        assert_eq!(lot.occupied_count(), 3);
+        assert_eq!(lot.reserved_count(), 1);
+        assert_eq!(lot.held_count(), 1);
+        assert_eq!(lot.free_count(), 1);
+        assert_eq!(
+            lot.free_count() + lot.occupied_count() + lot.reserved_count() + lot.held_count(),
+            lot.spots.len()
+        );
+    }
+
+    #[test]
+    fn park_spread_picks_the_most_isolated_spot() {
+        let mut lot = ParkingLot::new(10);
+        lot.park_car_in_spot(0).unwrap();
+        lot.park_car_in_spot(9).unwrap();
+        // Spots 4 and 5 are both four away from the nearest car; the lower ID wins.
+        assert_eq!(lot.park_spread("AB12".to_string()), Ok(4));
+        lot.park_car_in_spot(1).unwrap();
+        assert_eq!(lot.park_spread("CD34".to_string()), Ok(6));
+    }
+
+    #[test]
+    fn maintenance_blocks_parking_and_reservations_but_not_removal() {
+        let mut lot = ParkingLot::new(3);
+        lot.park_car().unwrap();
+        lot.set_maintenance(true);
+        assert_eq!(lot.park_car(), Err("Lot is closed for maintenance"));
+        assert_eq!(lot.park_car_in_spot(1), Err("Lot is closed for maintenance"));
+        assert_eq!(lot.reserve_spot(2, "amy".to_string()), Err("Lot is closed for maintenance"));
+        assert!(lot.remove_car(0).is_ok());
+        lot.set_maintenance(false);
+        assert_eq!(lot.park_car(), Ok(0));
+    }
+
+    #[test]
+    fn next_available_after_never_wraps() {
+        let mut lot = ParkingLot::new(5);
+        lot.park_car_in_spot(3).unwrap();
+        assert_eq!(lot.next_available_after(3), Some(4));
+        lot.park_car_in_spot(4).unwrap();
+        assert_eq!(lot.next_available_after(3), None);
+        assert_eq!(lot.next_available_after(0), Some(0));
+    }
+
+    #[test]
+    fn waitlist_is_promoted_when_a_reservation_is_claimed() {
+        let mut lot = ParkingLot::new(3);
+        lot.reserve_spot(1, "amy".to_string()).unwrap();
+        lot.queue_reservation(1, "ben".to_string()).unwrap();
+        lot.claim_reservation(1, "amy").unwrap();
+        assert_eq!(lot.reservations.get(&1).map(String::as_str), Some("ben"));
+        assert!(!lot.reservation_queues.contains_key(&1));
+    }
+
+    #[test]
+    fn held_spots_are_unavailable_until_the_hold_expires() {
+        let mut lot = ParkingLot::new(3);
+        lot.hold_spot(0, Duration::from_secs(600)).unwrap();
+        lot.hold_spot(1, Duration::ZERO).unwrap();
+        assert_eq!(lot.park_car_in_spot(0), Err("Spot is held for another customer"));
+        assert_eq!(lot.reserve_spot(0, "mallory".to_string()), Err("Spot is held for another customer"));
+        assert_eq!(
+            lot.reserve_or_suggest(0, "mallory".to_string()),
+            Err(("Spot is held for another customer", vec![2]))
+        );
+        assert_eq!(lot.hold_spot(2, Duration::MAX), Err("Hold is too long"));
+        assert_eq!(lot.release_expired_holds(), vec![1]);
+        assert_eq!(lot.park_car(), Ok(1));
+        assert_eq!(lot.spots[0].status(), "Held");
+    }
+
+    #[test]
+    fn failed_transaction_leaves_the_lot_unchanged() {
+        let mut lot = ParkingLot::new(4);
+        lot.park_car_in_spot(3).unwrap();
+        let before = lot.snapshot_string();
+        let ops = [
+            ParkingOp::Park(0),
+            ParkingOp::Reserve(1, "amy".to_string()),
+            ParkingOp::Park(3),
+        ];
+        assert_eq!(lot.apply_transaction(&ops), Err("Spot already occupied or reserved"));
+        assert_eq!(lot.snapshot_string(), before);
+    }
+
+    #[test]
+    fn confirmed_pending_reservation_survives_expiry() {
+        let mut lot = ParkingLot::new(2);
+        lot.reserve_pending(0, "amy".to_string(), Duration::ZERO).unwrap();
+        lot.confirm_reservation(0).unwrap();
+        assert!(lot.expire_pending_reservations().is_empty());
+        assert!(lot.spots[0].reserved);
+    }
+
+    #[test]
+    fn unconfirmed_pending_reservation_expires() {
+        let mut lot = ParkingLot::new(2);
+        lot.reserve_pending(0, "amy".to_string(), Duration::ZERO).unwrap();
+        lot.reserve_pending(1, "ben".to_string(), Duration::from_secs(600)).unwrap();
+        assert_eq!(lot.expire_pending_reservations(), vec![0]);
+        assert!(!lot.spots[0].reserved);
+        assert!(lot.spots[1].reserved);
+        assert_eq!(lot.confirm_reservation(0), Err("Spot has no reservation pending payment"));
+        assert_eq!(lot.reserve_pending(0, "cat".to_string(), Duration::MAX), Err("Payment window is too long"));
+        assert!(!lot.spots[0].reserved);
+    }
+
+    #[test]
+    fn nearest_including_reserved_flags_the_reserved_spot() {
+        let mut lot = ParkingLot::new(5);
+        lot.reserve_spot(2, "amy".to_string()).unwrap();
+        let (spot, reserved) = lot.find_nearest_including_reserved(2).unwrap();
+        assert_eq!((spot.id, reserved), (2, true));
+        assert_eq!(lot.find_nearest_available_spot(2).map(|spot| spot.id), Some(1));
+    }
+
+    #[test]
+    fn every_request_round_trips_through_handle_request() {
+        let mut lot = ParkingLot::new(4);
+        assert_eq!(lot.handle_request("park".parse().unwrap()), Response::Parked(0));
+        assert_eq!(lot.handle_request("park 2".parse().unwrap()), Response::Parked(2));
+        assert_eq!(lot.handle_request("remove 2".parse().unwrap()), Response::Removed { deferred: Vec::new(), promoted: None });
+        let code = match lot.handle_request("reserve 1 Amy Lee".parse().unwrap()) {
+            Response::Reserved { id: 1, code } => code,
+            other => panic!("unexpected response {:?}", other),
+        };
+        assert_eq!(
+            lot.handle_request(Request::Inspect(1)),
+            Response::Spot { id: 1, status: "Reserved", plate: None, reservation: Some("Amy Lee".to_string()) }
+        );
+        assert_eq!(lot.handle_request(Request::ClaimByCode(code, "AB12".to_string())), Response::Parked(1));
+        lot.reserve_spot(3, "ben".to_string()).unwrap();
+        assert_eq!(lot.handle_request("cancel 3".parse().unwrap()), Response::Cancelled { promoted: None });
+        assert_eq!(lot.handle_request("hold 3 5".parse().unwrap()), Response::Held(3));
+        assert_eq!(
+            lot.handle_request("status".parse().unwrap()),
+            Response::Status { total: 4, occupied: 2, reserved: 0, held: 1, free: 1 }
+        );
+        assert_eq!(lot.handle_request("remove 2".parse().unwrap()), Response::Error("Spot not found or already empty"));
+    }
+
+    #[test]
+    fn responses_render_as_json() {
+        assert_eq!(Response::Parked(3).to_json(), r#"{"result":"parked","id":3}"#);
+        assert_eq!(
+            Response::Removed { deferred: vec![1, 4], promoted: None }.to_json(),
+            r#"{"result":"removed","deferred":[1,4],"promoted":null}"#
+        );
+        assert_eq!(
+            Response::Error("Invalid spot ID").to_json(),
+            r#"{"result":"error","message":"Invalid spot ID"}"#
+        );
+    }
+
+    #[test]
+    fn drive_up_reservation_ends_when_the_car_leaves() {
+        let mut lot = ParkingLot::new(3);
+        lot.reserve_and_occupy(1, "amy".to_string(), "AB12".to_string()).unwrap();
+        assert_eq!(lot.spots[1].status(), "Occupied (reserved)");
+        lot.remove_car(1).unwrap();
+        assert_eq!(lot.spots[1].status(), "Available");
+        assert!(lot.reservations.is_empty());
+    }
+
+    #[test]
+    fn promoted_reservation_survives_the_drive_up_car_leaving() {
+        let mut lot = ParkingLot::new(3);
+        lot.reserve_and_occupy(1, "amy".to_string(), "AB12".to_string()).unwrap();
+        lot.queue_reservation(1, "ben".to_string()).unwrap();
+        lot.queue_reservation(1, "cat".to_string()).unwrap();
+        assert_eq!(lot.cancel_reservation(1), Ok(Some("ben".to_string())));
+        lot.remove_car(1).unwrap();
+        assert_eq!(lot.spots[1].status(), "Reserved");
+        assert_eq!(lot.reservations.get(&1).map(String::as_str), Some("ben"));
+    }
+
+    #[test]
+    fn claim_by_code_rejects_wrong_and_stale_codes() {
+        let mut lot = ParkingLot::new(3);
+        lot.schedule_reservation(1, "amy".to_string(), Instant::now()).unwrap();
+        let code = lot.confirmation_code(1).unwrap().to_string();
+        assert_eq!(lot.claim_by_code("NOPE00", "AB12".to_string()), Err("No reservation with that confirmation code"));
+        assert_eq!(lot.activate_scheduled(Instant::now()), vec![1]);
+        assert_eq!(lot.confirmation_code(1), None);
+        lot.remove_car(1).unwrap();
+        assert_eq!(lot.claim_by_code(&code, "AB12".to_string()), Err("No reservation with that confirmation code"));
+        assert!(!lot.spots[1].occupied);
+    }
+
+    #[test]
+    fn occupancy_ratio_handles_an_empty_lot() {
+        assert_eq!(ParkingLot::new(0).occupancy_ratio(), 0.0);
+        let mut lot = ParkingLot::new(4);
+        lot.park_car().unwrap();
+        assert_eq!(lot.occupancy_ratio(), 0.25);
+    }
+
+    #[test]
+    fn compaction_moves_do_not_count_as_uses() {
+        let mut lot = ParkingLot::new(5);
+        lot.park_car_in_spot(4).unwrap();
+        assert_eq!(lot.compact(), vec![(4, 0)]);
+        let uses: Vec<u32> = lot.spots.iter().map(|spot| spot.use_count).collect();
+        assert_eq!(uses, vec![0, 0, 0, 0, 1]);
+        assert!(lot.spots[0].occupied);
+        assert_eq!(lot.fill_order(), &[4]);
+    }
+
+    #[test]
+    fn modified_since_reports_only_touched_spots() {
+        let mut lot = ParkingLot::new(4);
+        let since = Instant::now();
+        std::thread::sleep(Duration::from_millis(5));
+        lot.park_car_in_spot(1).unwrap();
+        lot.reserve_spot(3, "amy".to_string()).unwrap();
+        assert_eq!(lot.modified_since(since), vec![1, 3]);
+    }
+
+    #[test]
+    fn override_clears_the_reservation_and_returns_its_details() {
+        let mut lot = ParkingLot::new(2);
+        lot.reserve_spot(0, "amy".to_string()).unwrap();
+        assert_eq!(lot.park_override_reservation(0, "AB12".to_string()), Ok("amy".to_string()));
+        assert_eq!(lot.spots[0].status(), "Occupied");
+        assert!(lot.reservations.is_empty());
+        assert_eq!(lot.confirmation_code(0), None);
+    }
+
+    #[test]
+    fn identical_lots_have_identical_snapshots() {
+        let build = || {
+            let mut lot = ParkingLot::new(3);
+            lot.park_preferred("AB12".to_string()).unwrap();
+            lot.reserve_spot(2, "amy".to_string()).unwrap();
+            lot
+        };
+        assert_eq!(build().snapshot_string(), build().snapshot_string());
+        let mut other = build();
+        other.park_car().unwrap();
+        assert_ne!(build().snapshot_string(), other.snapshot_string());
+    }
+
+    #[test]
+    fn auto_references_are_sequential() {
+        let mut lot = ParkingLot::new(3);
+        assert_eq!(lot.reserve_spot_auto(0), Ok("RES-0001".to_string()));
+        assert!(lot.reserve_spot_auto(0).is_err());
+        assert_eq!(lot.reserve_spot_auto(2), Ok("RES-0002".to_string()));
+        assert_eq!(lot.reservations.get(&2).map(String::as_str), Some("RES-0002"));
+    }
+
+    #[test]
+    fn suggestions_are_offered_only_for_a_taken_spot() {
+        let mut lot = ParkingLot::new(5);
+        lot.park_car_in_spot(2).unwrap();
+        assert_eq!(
+            lot.reserve_or_suggest(2, "Alice".to_string()),
+            Err(("Spot already occupied or reserved", vec![1, 3, 0]))
+        );
+        assert_eq!(lot.reserve_or_suggest(9, "Alice".to_string()), Err(("Invalid spot ID", Vec::new())));
+        lot.max_total_reservations = Some(0);
+        assert_eq!(
+            lot.reserve_or_suggest(4, "Alice".to_string()),
+            Err(("Reservation quota for the lot is full", Vec::new()))
+        );
+    }
+
+    #[test]
+    fn reservation_quota_leaves_spots_for_walk_ins() {
+        let mut lot = ParkingLot::new(3);
+        lot.max_total_reservations = Some(2);
+        lot.reserve_spot(0, "Alice".to_string()).unwrap();
+        lot.reserve_spot(1, "Bob".to_string()).unwrap();
+        assert_eq!(lot.reservation_quota_left(), 0);
+        assert_eq!(lot.reserve_spot(2, "Carol".to_string()), Err("Reservation quota for the lot is full"));
+        assert_eq!(lot.park_car(), Ok(2));
+    }
+
+    #[test]
+    fn turnover_rate_is_cars_per_spot_per_hour() {
+        let mut lot = ParkingLot::new(2);
+        lot.completed_stays.extend([Duration::from_secs(600); 4]);
+        assert_eq!(lot.turnover_rate(lot.opened_at), 0.0);
+        assert_eq!(lot.turnover_rate(lot.opened_at + Duration::from_secs(3600)), 2.0);
+    }
+
+    #[test]
+    fn cancel_handler_skips_rolled_back_transactions() {
+        let mut lot = ParkingLot::new(3);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        lot.set_cancel_handler(move |id, details| log.borrow_mut().push((id, details.to_string())));
+        lot.reserve_spot(0, "Alice".to_string()).unwrap();
+        lot.cancel_reservation(0).unwrap();
+        lot.reserve_spot(1, "Bob".to_string()).unwrap();
+        let ops = [ParkingOp::CancelReservation(1), ParkingOp::Park(9)];
+        assert!(lot.apply_transaction(&ops).is_err());
+        assert!(lot.spots[1].reserved);
+        lot.apply_transaction(&[ParkingOp::CancelReservation(1)]).unwrap();
+        lot.reserve_pending(2, "Carol".to_string(), Duration::ZERO).unwrap();
+        assert_eq!(lot.expire_pending_reservations(), vec![2]);
+        lot.reserve_spot(0, "Dave".to_string()).unwrap();
+        assert_eq!(lot.park_override_reservation(0, "AB123".to_string()), Ok("Dave".to_string()));
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (0, "Alice".to_string()),
+                (1, "Bob".to_string()),
+                (2, "Carol".to_string()),
+                (0, "Dave".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn usage_gini_measures_uneven_use() {
+        let mut lot = ParkingLot::new(4);
+        assert_eq!(lot.usage_gini(), 0.0);
+        lot.spots[3].use_count = 4;
+        assert_eq!(lot.usage_gini(), 0.75);
+        for spot in &mut lot.spots {
+            spot.use_count = 2;
+        }
+        assert_eq!(lot.usage_gini(), 0.0);
+    }
+
+    #[test]
+    fn is_parked_ignores_case_and_punctuation() {
+        let mut lot = ParkingLot::new(2);
+        lot.park_preferred("ab-123".to_string()).unwrap();
+        assert!(lot.is_parked("AB123"));
+        assert!(lot.is_parked("ab 123"));
+        assert!(!lot.is_parked("XY999"));
+        lot.remove_car(0).unwrap();
+        assert!(!lot.is_parked("AB123"));
+    }
+
+    #[test]
+    fn session_duration_counts_from_opening() {
+        let lot = ParkingLot::new(1);
+        assert_eq!(lot.session_duration(lot.opened_at + Duration::from_secs(90)), Duration::from_secs(90));
+        assert_eq!(lot.session_duration(lot.opened_at), Duration::ZERO);
+    }
+
+    #[test]
+    fn merge_shifts_the_other_lot_after_this_one() {
+        let mut lot = ParkingLot::new(2);
+        lot.park_car_in_spot(0).unwrap();
+        let mut other = ParkingLot::new(3);
+        other.park_car_in_spot(0).unwrap();
+        other.reserve_spot(2, "Alice".to_string()).unwrap();
+        assert_eq!(lot.merge(other.clone(), 1), Err("Merged spot IDs would collide with existing spots"));
+        assert_eq!(lot.merge(other.clone(), 3), Err("Merged spot IDs would leave a gap after existing spots"));
+        lot.merge(other, 2).unwrap();
+        assert_eq!(lot.spots.len(), 5);
+        assert_eq!(lot.occupied_count(), 2);
+        assert_eq!(lot.reserved_count(), 1);
+        assert!(lot.spots[2].occupied);
+        assert_eq!(lot.reservations.get(&4).map(String::as_str), Some("Alice"));
+
+        let mut first = ParkingLot::new(1);
+        first.reserve_spot(0, "Bob".to_string()).unwrap();
+        let mut second = ParkingLot::new(1);
+        second.reserve_spot(0, "Carol".to_string()).unwrap();
+        first.merge(second, 1).unwrap();
+        assert_eq!(first.reservations.get(&0).map(String::as_str), Some("Bob"));
+        assert_eq!(first.reservations.get(&1).map(String::as_str), Some("Carol"));
+        let carol = first.confirmation_code(1).unwrap().to_string();
+        assert_ne!(first.confirmation_code(0), Some(carol.as_str()));
+        assert_eq!(first.claim_by_code(&carol, "AB123".to_string()), Ok(1));
+    }
+
+    #[test]
+    fn last_known_spot_outlives_the_stay() {
+        let mut lot = ParkingLot::new(3);
+        assert_eq!(lot.last_known_spot("AB123"), None);
+        lot.park_car_in_spot(0).unwrap();
+        let id = lot.park_preferred("ab-123".to_string()).unwrap();
+        lot.remove_car(id).unwrap();
+        assert_eq!(lot.last_known_spot("AB 123"), Some(id));
+        assert!(!lot.is_parked("AB123"));
+    }
+
+    #[test]
+    fn plates_are_normalized_and_empty_ones_rejected() {
+        assert_eq!(normalize_plate(" ab-12 3 "), "AB123");
+        assert_eq!(normalize_plate("Ab.123"), "AB123");
+        let mut lot = ParkingLot::new(2);
+        assert_eq!(lot.plate_for("ab 123"), Ok("AB123".to_string()));
+        assert_eq!(lot.park_preferred(" - ".to_string()), Err("Invalid license plate"));
+        lot.strict_plates = true;
+        assert_eq!(lot.plate_for(""), Err("Invalid license plate"));
+        assert_eq!(lot.plate_for("A"), Err("Invalid license plate"));
+        assert_eq!(lot.plate_for("ab-123"), Ok("AB123".to_string()));
+    }
+
+    #[test]
+    fn deferred_reservations_follow_a_compacted_car() {
+        let mut lot = ParkingLot::new(5);
+        for id in [1, 3] {
+            lot.park_car_in_spot(id).unwrap();
+        }
+        lot.reserve_on_free(3, 4, "Alice".to_string()).unwrap();
+        lot.reserve_on_free(1, 2, "Bob".to_string()).unwrap();
+        assert_eq!(lot.compact(), vec![(3, 0)]);
+        assert_eq!(lot.remove_car(1).map(|removal| removal.deferred), Ok(vec![2]));
+        assert!(lot.spots[2].reserved);
+        assert!(!lot.spots[4].reserved);
+        assert_eq!(lot.remove_car(0).map(|removal| removal.deferred), Ok(vec![4]));
+        assert_eq!(lot.reservations.get(&4).map(String::as_str), Some("Alice"));
+        assert!(lot.deferred_reservations.is_empty());
+    }
+
+    #[test]
+    fn status_breakdown_sums_to_the_lot_size() {
+        let mut lot = ParkingLot::new(6);
+        lot.park_car_in_spot(0).unwrap();
+        lot.reserve_spot(1, "Alice".to_string()).unwrap();
+        lot.park_override_reservation(1, "AB123".to_string()).unwrap();
+        lot.reserve_spot(2, "Bob".to_string()).unwrap();
+        lot.hold_spot(3, Duration::from_secs(60)).unwrap();
+        let breakdown = lot.status_breakdown();
+        assert_eq!(breakdown.values().sum::<usize>(), lot.spots.len());
+        assert_eq!(breakdown.get("Occupied"), Some(&2));
+        assert_eq!(breakdown.get("Reserved"), Some(&1));
+        assert_eq!(breakdown.get("Held"), Some(&1));
+        assert_eq!(breakdown.get("Available"), Some(&2));
+    }
+
+    #[test]
+    fn minutes_to_duration_rejects_overflow() {
+        assert_eq!(minutes_to_duration(2), Some(Duration::from_secs(120)));
+        assert_eq!(minutes_to_duration(400_000_000_000_000_000), None);
+    }
+
+    #[test]
+    fn removing_a_drive_up_car_reports_the_promoted_reservation() {
+        let mut lot = ParkingLot::new(3);
+        lot.reserve_and_occupy(1, "amy".to_string(), "AB12".to_string()).unwrap();
+        lot.queue_reservation(1, "ben".to_string()).unwrap();
+        let removal = lot.remove_car(1).unwrap();
+        assert_eq!(removal, Removal { deferred: Vec::new(), promoted: Some("ben".to_string()) });
+        assert_eq!(lot.reservations.get(&1).map(String::as_str), Some("ben"));
+    }
+
+    #[test]
+    fn requests_round_trip_through_json() {
+        let requests = [
+            Request::Park,
+            Request::ParkInSpot(2),
+            Request::Remove(2),
+            Request::Reserve(1, "Amy \"A\" Lee\n".to_string()),
+            Request::CancelReservation(1),
+            Request::ClaimByCode("00ABC".to_string(), "AB12".to_string()),
+            Request::Hold(3, Duration::from_secs(300)),
+            Request::Inspect(1),
+            Request::Status,
+        ];
+        for request in requests {
+            assert_eq!(Request::from_json(&request.to_json()), Ok(request));
+        }
+        assert_eq!(
+            Request::from_json(r#" { "id" : 3, "action" : "reserve", "details" : "Amy \u00e9" } "#),
+            Ok(Request::Reserve(3, "Amy é".to_string()))
+        );
+        assert_eq!(Request::from_json(r#"{"action":"park","id":}"#), Err("Malformed JSON object"));
+        assert_eq!(Request::from_json(r#"{"action":"fly"}"#), Err(UNKNOWN_REQUEST));
+    }
+
+    #[test]
+    fn hold_requests_reject_overflowing_minutes() {
+        let expected = Err("Expected the number of minutes to hold");
+        assert_eq!("hold 3 999999999999999999".parse::<Request>(), expected);
+        assert_eq!(Request::from_json(r#"{"action":"hold","id":3,"minutes":999999999999999999}"#), expected);
+    }
+
+    #[test]
+    fn park_where_honours_the_predicate() {
+        let mut lot = ParkingLot::new(5);
+        lot.park_car_in_spot(0).unwrap();
+        assert_eq!(lot.park_where(|spot| spot.id % 2 == 0), Ok(2));
+        assert_eq!(lot.find_spot_where(|spot| spot.id % 2 == 0 && !spot.occupied).map(|spot| spot.id), Some(4));
+        lot.park_car_in_spot(4).unwrap();
+        assert_eq!(lot.park_where(|spot| spot.id % 2 == 0), Err("No available spots"));
+    }
+
+    #[test]
+    fn reserve_where_skips_taken_spots_in_a_mixed_lot() {
+        let mut lot = ParkingLot::new(8);
+        lot.park_car_in_spot(0).unwrap();
+        lot.reserve_spot(1, "Alice".to_string()).unwrap();
+        lot.hold_spot(2, Duration::from_secs(600)).unwrap();
+        lot.set_vip(3, true).unwrap();
+        assert_eq!(lot.reserve_where(|spot| spot.id != 5, "Block".to_string()), vec![3, 4, 6, 7]);
+        assert_eq!(lot.reservations.get(&1).map(String::as_str), Some("Alice"));
+        assert!(!lot.spots[0].reserved && !lot.spots[2].reserved && !lot.spots[5].reserved);
+        assert_eq!(lot.spots[2].status(), "Held");
+
+        let mut capped = ParkingLot::new(5);
+        capped.max_total_reservations = Some(3);
+        capped.reserve_spot(0, "Alice".to_string()).unwrap();
+        assert_eq!(capped.reserve_where(|_| true, "Block".to_string()), vec![1, 2]);
+        assert_eq!(capped.reserve_where(|_| true, "Block".to_string()), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn fill_order_records_every_park_in_sequence() {
+        let mut lot = ParkingLot::new(4);
+        lot.park_car().unwrap();
+        lot.park_car_in_spot(3).unwrap();
+        lot.park_car().unwrap();
+        lot.remove_car(0).unwrap();
+        assert_eq!(lot.fill_order(), &[0, 3, 1]);
+        lot.park_car().unwrap();
+        lot.park_car().unwrap();
+        assert_eq!(lot.fill_order(), &[0, 3, 1, 0, 2]);
+    }
+
+    #[test]
+    fn deferred_reservations_wait_out_maintenance_and_the_quota() {
+        let mut lot = ParkingLot::new(4);
+        lot.park_car_in_spot(0).unwrap();
+        lot.park_car_in_spot(1).unwrap();
+        lot.reserve_on_free(0, 2, "Alice".to_string()).unwrap();
+        lot.reserve_on_free(1, 3, "Bob".to_string()).unwrap();
+        lot.maintenance_mode = true;
+        assert_eq!(lot.remove_car(0).map(|removal| removal.deferred), Ok(Vec::new()));
+        assert_eq!(lot.retry_deferred(), Vec::<usize>::new());
+        lot.maintenance_mode = false;
+        lot.max_total_reservations = Some(0);
+        assert_eq!(lot.retry_deferred(), Vec::<usize>::new());
+        lot.max_total_reservations = None;
+        assert_eq!(lot.retry_deferred(), vec![2]);
+        assert_eq!(lot.reservations.get(&2).map(String::as_str), Some("Alice"));
+        assert_eq!(lot.deferred_reservations, vec![(1, 3, "Bob".to_string())]);
+    }
+}